@@ -0,0 +1,458 @@
+use chrono::{Duration, NaiveDate};
+
+use coda::{Coda, Movement};
+use utils::{format_amount, signed_amount, Sign};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AccountingFormat {
+    Beancount,
+    Ledger,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct AccountingOptions {
+    // Default to Assets:Bank:<account number>
+    pub asset_account: Option<String>,
+    // Counter accounts of the debits and of the credits
+    pub expense_account: String,
+    pub income_account: String,
+    // Counter account of the opening balance of the first statement of each asset account
+    pub opening_account: String,
+    // The balances of filtered statements do not match their entries, they are left out
+    pub assert_balances: bool,
+}
+
+impl Default for AccountingOptions {
+    fn default() -> AccountingOptions {
+        AccountingOptions {
+            asset_account: None,
+            expense_account: String::from("Expenses:Unknown"),
+            income_account: String::from("Income:Unknown"),
+            opening_account: String::from("Equity:Opening-Balances"),
+            assert_balances: true,
+        }
+    }
+}
+
+impl AccountingOptions {
+    fn asset_account(&self, coda: &Coda) -> String {
+        self.asset_account.as_ref().map_or_else(
+            || format!("Assets:Bank:{}", coda.old_balance.account.number()),
+            |a| a.clone(),
+        )
+    }
+
+    fn counter_account(&self, movement: &Movement) -> &str {
+        match movement.sign() {
+            Sign::Credit => &self.income_account,
+            Sign::Debit => &self.expense_account,
+        }
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn single_line(text: &str) -> String {
+    text.replace('\n', " ").trim().to_string()
+}
+
+fn narration(movement: &Movement) -> String {
+    match movement.structured_reference() {
+        Some(reference) => reference,
+        None => single_line(&movement.communication),
+    }
+}
+
+fn payee(entry: &[&Movement]) -> Option<String> {
+    Coda::entry_counterparty(entry)
+        .and_then(|m| m.counterparty_name.as_ref())
+        .map(|name| single_line(name))
+        .filter(|name| !name.is_empty())
+}
+
+// The old balance is the balance at the end of its date, it is asserted the day after unless the statement already
+// has entries booked on that day or before
+fn opening_date(coda: &Coda) -> NaiveDate {
+    let day_after = coda.old_balance.old_balance_date + Duration::days(1);
+    coda.movements.iter().map(|m| m.entry_date).fold(day_after, |date, entry_date| date.min(entry_date))
+}
+
+fn write_beancount_balance(output: &mut Vec<String>, date: &NaiveDate, account: &str, amount: i64, currency: &str) {
+    output.push(format!(
+        "{} balance {}  {} {}",
+        date.format("%Y-%m-%d"),
+        account,
+        format_amount(amount, '.'),
+        currency
+    ));
+    output.push(String::new());
+}
+
+// Beancount refuses the postings and balances of accounts that are not open, all the accounts are opened on the
+// first date of the files (the day before the first opening balance, where it is padded), asset accounts with their
+// currencies
+fn write_beancount_open(output: &mut Vec<String>, coda_list: &[Coda], options: &AccountingOptions) {
+    let first_date = coda_list.iter().map(|coda| opening_date(coda) - Duration::days(1)).min();
+    let date = match first_date {
+        Some(date) => date,
+        None => return,
+    };
+    let mut asset_accounts: Vec<(String, Vec<&str>)> = Vec::new();
+    for coda in coda_list {
+        let account = options.asset_account(coda);
+        let currency = coda.old_balance.account.currency();
+        match asset_accounts.iter_mut().find(|&&mut (ref a, _)| *a == account) {
+            Some(&mut (_, ref mut currencies)) => {
+                if !currencies.contains(&currency) {
+                    currencies.push(currency);
+                }
+            }
+            None => asset_accounts.push((account, vec![currency])),
+        }
+    }
+    for (account, currencies) in asset_accounts {
+        output.push(format!("{} open {} {}", date.format("%Y-%m-%d"), account, currencies.join(",")));
+    }
+    output.push(format!("{} open {}", date.format("%Y-%m-%d"), options.expense_account));
+    output.push(format!("{} open {}", date.format("%Y-%m-%d"), options.income_account));
+    output.push(format!("{} open {}", date.format("%Y-%m-%d"), options.opening_account));
+    output.push(String::new());
+}
+
+// The balance assertion must be preceded by a pad the day before, an unused pad is an error so a zero opening
+// balance has none
+fn write_beancount_pad(
+    output: &mut Vec<String>,
+    date: &NaiveDate,
+    account: &str,
+    amount: i64,
+    options: &AccountingOptions,
+) {
+    if amount != 0 {
+        let pad_date = *date - Duration::days(1);
+        output.push(format!("{} pad {} {}", pad_date.format("%Y-%m-%d"), account, options.opening_account));
+        output.push(String::new());
+    }
+}
+
+fn write_beancount_transaction(
+    output: &mut Vec<String>,
+    entry: &[&Movement],
+    account: &str,
+    currency: &str,
+    options: &AccountingOptions,
+) {
+    let movement = entry[0];
+    let header = match payee(entry) {
+        Some(payee) => format!("{} {}", quote(&payee), quote(&narration(movement))),
+        None => quote(&narration(movement)),
+    };
+    output.push(format!("{} * {}", movement.entry_date.format("%Y-%m-%d"), header));
+    output.push(format!("  bank_reference: {}", quote(movement.bank_reference.trim())));
+    output.push(format!("  transaction_code: {}", quote(&movement.transaction_code)));
+    output.push(format!(
+        "  {}  {} {}",
+        account,
+        format_amount(movement.signed_amount(), '.'),
+        currency
+    ));
+    output.push(format!("  {}", options.counter_account(movement)));
+    output.push(String::new());
+}
+
+fn write_ledger_balance(output: &mut Vec<String>, date: &NaiveDate, account: &str, amount: i64, currency: &str) {
+    output.push(format!("{} Balance assertion", date.format("%Y/%m/%d")));
+    output.push(format!(
+        "    {}  0 {} = {} {}",
+        account,
+        currency,
+        format_amount(amount, '.'),
+        currency
+    ));
+    output.push(String::new());
+}
+
+fn write_ledger_opening(
+    output: &mut Vec<String>,
+    date: &NaiveDate,
+    account: &str,
+    amount: i64,
+    currency: &str,
+    options: &AccountingOptions,
+) {
+    if amount != 0 {
+        output.push(format!("{} * Opening balance", date.format("%Y/%m/%d")));
+        output.push(format!("    {}  {} {}", account, format_amount(amount, '.'), currency));
+        output.push(format!("    {}", options.opening_account));
+        output.push(String::new());
+    }
+}
+
+fn write_ledger_transaction(
+    output: &mut Vec<String>,
+    entry: &[&Movement],
+    account: &str,
+    currency: &str,
+    options: &AccountingOptions,
+) {
+    let movement = entry[0];
+    let narration = narration(movement);
+    let payee = payee(entry).unwrap_or_else(|| narration.clone());
+    output.push(format!("{} * {}", movement.entry_date.format("%Y/%m/%d"), payee));
+    if !narration.is_empty() {
+        output.push(format!("    ; {}", narration));
+    }
+    output.push(format!("    ; bank_reference: {}", movement.bank_reference.trim()));
+    output.push(format!("    ; transaction_code: {}", movement.transaction_code));
+    output.push(format!(
+        "    {}  {} {}",
+        account,
+        format_amount(movement.signed_amount(), '.'),
+        currency
+    ));
+    output.push(format!("    {}", options.counter_account(movement)));
+    output.push(String::new());
+}
+
+// Beancount checks a balance at the beginning of the day, the closing balance is asserted the day after.
+// The first statement of an asset account brings its opening balance from the opening account.
+// Filtered statements are written without balances.
+fn write_statement(
+    output: &mut Vec<String>,
+    coda: &Coda,
+    format: AccountingFormat,
+    options: &AccountingOptions,
+    first: bool,
+) {
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+    let currency = old_balance.account.currency();
+    let account = options.asset_account(coda);
+    let opening = signed_amount(&old_balance.old_balance_sign, old_balance.old_balance);
+    let closing = signed_amount(&new_balance.new_balance_sign, new_balance.new_balance);
+    let opening_date = opening_date(coda);
+
+    let balances = options.assert_balances;
+
+    match format {
+        AccountingFormat::Beancount => {
+            if balances {
+                if first {
+                    write_beancount_pad(output, &opening_date, &account, opening, options);
+                }
+                write_beancount_balance(output, &opening_date, &account, opening, currency);
+            }
+            for entry in coda.entries() {
+                write_beancount_transaction(output, &entry, &account, currency, options);
+            }
+            if balances {
+                let closing_date = new_balance.new_balance_date + Duration::days(1);
+                write_beancount_balance(output, &closing_date, &account, closing, currency);
+            }
+        }
+        AccountingFormat::Ledger => {
+            if balances {
+                if first {
+                    write_ledger_opening(output, &opening_date, &account, opening, currency, options);
+                }
+                write_ledger_balance(output, &opening_date, &account, opening, currency);
+            }
+            for entry in coda.entries() {
+                write_ledger_transaction(output, &entry, &account, currency, options);
+            }
+            if balances {
+                write_ledger_balance(output, &new_balance.new_balance_date, &account, closing, currency);
+            }
+        }
+    }
+}
+
+// The CODA account is the asset account (default to Assets:Bank:<account number>), movements are balanced against
+// the income account (credits) or the expense account (debits)
+pub fn to_accounting(coda_list: &[Coda], format: AccountingFormat, options: &AccountingOptions) -> String {
+    let mut output = Vec::new();
+    if format == AccountingFormat::Beancount {
+        write_beancount_open(&mut output, coda_list, options);
+    }
+    let mut opened: Vec<String> = Vec::new();
+    for coda in coda_list {
+        let account = options.asset_account(coda);
+        let first = !opened.contains(&account);
+        if first {
+            opened.push(account);
+        }
+        write_statement(&mut output, coda, format, options, first);
+    }
+    output.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod test_accounting {
+    use super::*;
+    use fixtures::{parse_coda, parse_coda_list};
+
+    fn asset_account(account: &str) -> AccountingOptions {
+        AccountingOptions {
+            asset_account: Some(String::from(account)),
+            ..AccountingOptions::default()
+        }
+    }
+
+    #[test]
+    fn to_beancount_valid() {
+        let actual = to_accounting(
+            &parse_coda_list(),
+            AccountingFormat::Beancount,
+            &asset_account("Assets:KBC:Current"),
+        );
+
+        let lines = actual.lines().skip(5).collect::<Vec<&str>>();
+        assert_eq!(lines[0], "2006-12-06 balance Assets:KBC:Current  0.000 EUR");
+        assert_eq!(
+            lines[2],
+            "2006-12-06 * \"BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI ON 495953\""
+        );
+        assert_eq!(lines[3], "  bank_reference: \"EPIB00048 AWIUBTKAPUO\"");
+        assert_eq!(lines[4], "  transaction_code: \"00799000\"");
+        assert_eq!(lines[5], "  Assets:KBC:Current  -2578.250 EUR");
+        assert_eq!(lines[6], "  Expenses:Unknown");
+        assert_eq!(
+            actual.ends_with("2006-12-08 balance Assets:KBC:Current  9405296.990 EUR\n\n"),
+            true,
+            "Closing balance should be asserted the day after"
+        );
+    }
+
+    #[test]
+    fn to_beancount_open() {
+        let mut coda_list = parse_coda_list();
+        coda_list.push(parse_coda());
+        coda_list[0].movements[0].entry_date = NaiveDate::from_ymd(2006, 12, 5);
+        let options = AccountingOptions {
+            asset_account: None,
+            expense_account: String::from("Expenses:Bank"),
+            income_account: String::from("Income:Sales"),
+            ..AccountingOptions::default()
+        };
+
+        let actual = to_accounting(&coda_list, AccountingFormat::Beancount, &options);
+
+        let lines = actual.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "2006-12-04 open Assets:Bank:435000000080 EUR", "Opened before the first posting");
+        assert_eq!(lines[1], "2006-12-04 open Expenses:Bank");
+        assert_eq!(lines[2], "2006-12-04 open Income:Sales");
+        assert_eq!(lines[3], "2006-12-04 open Equity:Opening-Balances");
+        assert_eq!(lines[4], "");
+        assert_eq!(actual.matches(" open ").count(), 4, "Accounts should be opened once");
+        assert_eq!(actual.contains("\n  Expenses:Bank\n"), true, "Debits should use the expense account");
+        assert_eq!(actual.contains("\n  Income:Sales\n"), true, "Credits should use the income account");
+    }
+
+    #[test]
+    fn to_beancount_payee() {
+        let actual = to_accounting(&parse_coda_list(), AccountingFormat::Beancount, &AccountingOptions::default());
+
+        assert_eq!(
+            actual.contains("* \"ECONOLER  S.A.\" \""),
+            true,
+            "Counterparty should be the payee"
+        );
+        assert_eq!(actual.contains("  Assets:Bank:435000000080  "), true, "Default asset account");
+    }
+
+    #[test]
+    fn to_ledger_valid() {
+        let actual = to_accounting(&parse_coda_list(), AccountingFormat::Ledger, &asset_account("Assets:KBC:Current"));
+
+        assert_eq!(actual.contains(" open "), false, "Ledger accounts need no open directive");
+
+        let lines = actual.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "2006/12/06 Balance assertion");
+        assert_eq!(lines[1], "    Assets:KBC:Current  0 EUR = 0.000 EUR");
+        assert_eq!(
+            lines[3],
+            "2006/12/06 * BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI ON 495953"
+        );
+        assert_eq!(lines[5], "    ; bank_reference: EPIB00048 AWIUBTKAPUO");
+        assert_eq!(lines[7], "    Assets:KBC:Current  -2578.250 EUR");
+        assert_eq!(lines[8], "    Expenses:Unknown");
+        assert_eq!(
+            actual.contains("    Assets:KBC:Current  0 EUR = 9405296.990 EUR\n"),
+            true,
+            "Closing balance should be asserted"
+        );
+    }
+
+    // A statement opening at 1500.000 EUR the day before its entries
+    fn parse_coda_opening() -> Vec<Coda> {
+        let mut coda_list = parse_coda_list();
+        coda_list[0].old_balance.old_balance = 1500000;
+        coda_list[0].old_balance.old_balance_date = NaiveDate::from_ymd(2006, 12, 5);
+        coda_list.push(parse_coda());
+        coda_list
+    }
+
+    #[test]
+    fn to_beancount_opening_balance() {
+        let options = AccountingOptions {
+            opening_account: String::from("Equity:Opening"),
+            ..asset_account("Assets:KBC:Current")
+        };
+
+        let actual = to_accounting(&parse_coda_opening(), AccountingFormat::Beancount, &options);
+
+        let lines = actual.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "2006-12-05 open Assets:KBC:Current EUR");
+        assert_eq!(lines[3], "2006-12-05 open Equity:Opening");
+        assert_eq!(lines[5], "2006-12-05 pad Assets:KBC:Current Equity:Opening");
+        assert_eq!(
+            lines[7],
+            "2006-12-06 balance Assets:KBC:Current  1500.000 EUR",
+            "The opening balance should be asserted the day after its date"
+        );
+        assert_eq!(actual.matches(" pad ").count(), 1, "Only the first statement of the account is padded");
+    }
+
+    #[test]
+    fn to_ledger_opening_balance() {
+        let actual = to_accounting(
+            &parse_coda_opening(),
+            AccountingFormat::Ledger,
+            &asset_account("Assets:KBC:Current"),
+        );
+
+        let lines = actual.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "2006/12/06 * Opening balance");
+        assert_eq!(lines[1], "    Assets:KBC:Current  1500.000 EUR");
+        assert_eq!(lines[2], "    Equity:Opening-Balances");
+        assert_eq!(lines[4], "2006/12/06 Balance assertion");
+        assert_eq!(lines[5], "    Assets:KBC:Current  0 EUR = 1500.000 EUR");
+        assert_eq!(actual.matches("Opening balance").count(), 1, "Only the first statement of the account opens it");
+    }
+
+    #[test]
+    fn to_accounting_without_balances() {
+        let options = AccountingOptions {
+            assert_balances: false,
+            ..AccountingOptions::default()
+        };
+        let mut coda_list = parse_coda_opening();
+        coda_list[0].movements.truncate(1);
+
+        let beancount = to_accounting(&coda_list, AccountingFormat::Beancount, &options);
+        let ledger = to_accounting(&coda_list, AccountingFormat::Ledger, &options);
+
+        assert_eq!(beancount.contains(" balance "), false, "Filtered statements should not assert balances");
+        assert_eq!(beancount.contains(" pad "), false);
+        assert_eq!(beancount.contains("  Assets:Bank:435000000080  -2578.250 EUR\n"), true);
+        assert_eq!(ledger.contains("Balance assertion"), false);
+        assert_eq!(ledger.contains("Opening balance"), false);
+    }
+
+    #[test]
+    fn quote_valid() {
+        assert_eq!(quote("SAY \"HI\""), "\"SAY \\\"HI\\\"\"");
+    }
+}
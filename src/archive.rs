@@ -0,0 +1,225 @@
+extern crate flate2;
+extern crate zip;
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use archive::flate2::read::MultiGzDecoder;
+use archive::zip::ZipArchive;
+use input::{has_extension, is_coda_content, CODA_START, JSON_EXTENSIONS};
+
+use errors::*;
+
+pub const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "gz"];
+
+// A file read from an archive, named archive!entry
+#[derive(PartialEq, Debug)]
+pub struct ArchiveEntry {
+    pub source: String,
+    pub content: Vec<u8>,
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    has_extension(path, &ARCHIVE_EXTENSIONS)
+}
+
+// A file of an archive is kept when it looks like a coda file (or a json file with --from-json)
+fn is_input_entry(name: &str, start: &[u8], from_json: bool) -> bool {
+    if from_json {
+        has_extension(Path::new(name), &JSON_EXTENSIONS)
+    } else {
+        is_coda_content(start)
+    }
+}
+
+// The start of a file is enough to recognize a coda file
+fn read_start<R: Read>(reader: R) -> ::std::io::Result<Vec<u8>> {
+    let mut start = Vec::new();
+    reader.take(CODA_START.len() as u64).read_to_end(&mut start)?;
+    Ok(start)
+}
+
+fn gzip_name(filename: &str) -> String {
+    Path::new(filename)
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().into_owned())
+}
+
+fn zip_holds_input(path: &Path, from_json: bool) -> ::std::io::Result<bool> {
+    let mut zip = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    for i in 0..zip.len() {
+        let entry = zip.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = String::from(entry.name());
+        if is_input_entry(&name, &read_start(entry)?, from_json) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn gzip_holds_input(path: &Path, from_json: bool) -> ::std::io::Result<bool> {
+    let start = read_start(MultiGzDecoder::new(BufReader::new(File::open(path)?)))?;
+    Ok(is_input_entry(&gzip_name(&path.to_string_lossy()), &start, from_json))
+}
+
+// Archives found by discovery are only read when they hold a coda file (or a json file with --from-json),
+// unreadable archives and archives of other files are skipped like any other file
+pub fn holds_input(path: &Path, from_json: bool) -> bool {
+    let holds = if has_extension(path, &["zip"]) {
+        zip_holds_input(path, from_json)
+    } else {
+        gzip_holds_input(path, from_json)
+    };
+    holds.unwrap_or(false)
+}
+
+// The entries of a zip file are kept when they look like coda files (or json files with --from-json),
+// other files of the bundle are skipped
+fn read_zip(filename: &str, from_json: bool) -> Result<Vec<ArchiveEntry>> {
+    let file = File::open(filename).chain_err(|| format!("Unable to open {}", filename))?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).chain_err(|| format!("Invalid zip archive {}", filename))?;
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)
+            .chain_err(|| format!("Unable to read entry {} of {}", i, filename))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = String::from(entry.name());
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .chain_err(|| format!("Unable to read {}!{}", filename, name))?;
+        if is_input_entry(&name, &content, from_json) {
+            entries.push(ArchiveEntry {
+                source: format!("{}!{}", filename, name),
+                content,
+            });
+        }
+    }
+    if entries.is_empty() {
+        return Err(format!("No coda file in archive {}", filename).into());
+    }
+    Ok(entries)
+}
+
+// A gzip file holds a single file, named as the archive without its .gz extension
+fn read_gzip(filename: &str) -> Result<Vec<ArchiveEntry>> {
+    let file = File::open(filename).chain_err(|| format!("Unable to open {}", filename))?;
+    let mut content = Vec::new();
+    MultiGzDecoder::new(BufReader::new(file))
+        .read_to_end(&mut content)
+        .chain_err(|| format!("Invalid gzip archive {}", filename))?;
+    let name = gzip_name(filename);
+    Ok(vec![ArchiveEntry {
+        source: format!("{}!{}", filename, name),
+        content,
+    }])
+}
+
+pub fn read_archive(filename: &str, from_json: bool) -> Result<Vec<ArchiveEntry>> {
+    if has_extension(Path::new(filename), &["zip"]) {
+        read_zip(filename, from_json)
+    } else {
+        read_gzip(filename)
+    }
+}
+
+#[cfg(test)]
+mod test_archive {
+    use std::env;
+    use std::fs::{read, remove_file};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::*;
+    use archive::flate2::write::GzEncoder;
+    use archive::flate2::Compression;
+    use archive::zip::write::FileOptions;
+    use archive::zip::ZipWriter;
+
+    fn temp_file(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("coda-rs-{}-{}", ::std::process::id(), name))
+    }
+
+    fn make_zip(name: &str) -> String {
+        let path = temp_file(name);
+        let coda = read("test-data/CODA.txt").unwrap();
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.add_directory("2024/", FileOptions::default()).unwrap();
+        zip.start_file("2024/first.CD2", FileOptions::default()).unwrap();
+        zip.write_all(&coda).unwrap();
+        zip.start_file("readme.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"Statements of 2024\n").unwrap();
+        zip.start_file("second.cod", FileOptions::default()).unwrap();
+        zip.write_all(&coda).unwrap();
+        zip.finish().unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn is_archive_valid() {
+        assert_eq!(is_archive(Path::new("statements.ZIP")), true);
+        assert_eq!(is_archive(Path::new("CODA.CD2.gz")), true);
+        assert_eq!(is_archive(Path::new("CODA.CD2")), false);
+    }
+
+    #[test]
+    fn read_zip_valid() {
+        let filename = make_zip("bundle.zip");
+
+        let actual = read_archive(&filename, false).unwrap();
+
+        assert_eq!(
+            actual.iter().map(|e| e.source.clone()).collect::<Vec<_>>(),
+            vec![format!("{}!2024/first.CD2", filename), format!("{}!second.cod", filename)],
+            "Only the coda files should be read"
+        );
+        assert_eq!(actual[0].content, read("test-data/CODA.txt").unwrap());
+        assert_eq!(read_archive(&filename, true).is_err(), true, "No json file in the archive");
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn read_gzip_valid() {
+        let path = temp_file("CODA.CD2.gz");
+        let mut gzip = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        gzip.write_all(&read("test-data/CODA.txt").unwrap()).unwrap();
+        gzip.finish().unwrap();
+        let filename = path.to_string_lossy().into_owned();
+
+        let actual = read_archive(&filename, false).unwrap();
+
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].source, format!("{}!coda-rs-{}-CODA.CD2", filename, ::std::process::id()));
+        assert_eq!(actual[0].content, read("test-data/CODA.txt").unwrap());
+        remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn holds_input_valid() {
+        let filename = make_zip("holds.zip");
+        let path = temp_file("statements.json.gz");
+        let mut gzip = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        gzip.write_all(b"[]").unwrap();
+        gzip.finish().unwrap();
+
+        assert_eq!(holds_input(Path::new(&filename), false), true);
+        assert_eq!(holds_input(Path::new(&filename), true), false, "No json file in the zip archive");
+        assert_eq!(holds_input(&path, true), true);
+        assert_eq!(holds_input(&path, false), false, "The gzip content is not a coda file");
+        assert_eq!(holds_input(Path::new("test-data/CODA.txt.gz"), false), false, "Missing archive");
+        remove_file(filename).unwrap();
+        remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_archive_invalid() {
+        assert_eq!(read_archive("test-data/CODA.txt.gz", false).is_err(), true, "Missing archive");
+        assert_eq!(read_archive("test-data/CODA.txt", false).is_err(), true, "Not a gzip file");
+    }
+}
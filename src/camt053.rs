@@ -0,0 +1,501 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use coda::{Account, Coda, Movement};
+use utils::{format_amount, Sign};
+use xml::XmlWriter;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CamtVersion {
+    V02,
+    V08,
+}
+
+impl CamtVersion {
+    pub fn namespace(&self) -> &str {
+        match *self {
+            CamtVersion::V02 => "urn:iso:std:iso:20022:tech:xsd:camt.053.001.02",
+            CamtVersion::V08 => "urn:iso:std:iso:20022:tech:xsd:camt.053.001.08",
+        }
+    }
+
+    // Financial institutions are identified by BIC up to .04 and by BICFI from .05 on
+    fn bic_tag(&self) -> &str {
+        match *self {
+            CamtVersion::V02 => "BIC",
+            CamtVersion::V08 => "BICFI",
+        }
+    }
+}
+
+impl FromStr for CamtVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<CamtVersion, String> {
+        match s {
+            "001.02" | "02" => Ok(CamtVersion::V02),
+            "001.08" | "08" => Ok(CamtVersion::V08),
+            _ => Err(format!("Invalid camt.053 version [{}]", s)),
+        }
+    }
+}
+
+fn format_date(date: &NaiveDate) -> String {
+    format!("{}", date.format("%Y-%m-%d"))
+}
+
+fn format_date_time(date: &NaiveDate) -> String {
+    format!("{}", date.format("%Y-%m-%dT00:00:00"))
+}
+
+fn credit_debit(sign: &Sign) -> &str {
+    match *sign {
+        Sign::Credit => "CRDT",
+        Sign::Debit => "DBIT",
+    }
+}
+
+// Max140Text zones, the communication lines of the continuation records are joined with a space
+fn text_chunks(text: &str) -> Vec<String> {
+    let text = text.replace('\n', " ");
+    let chars = text.trim().chars().collect::<Vec<char>>();
+    chars
+        .chunks(140)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect()
+}
+
+fn write_amount(writer: &mut XmlWriter, name: &str, currency: &str, amount: u64) {
+    writer.text_attr(name, "Ccy", currency, &format_amount(amount as i64, '.'));
+}
+
+fn write_account_id(writer: &mut XmlWriter, account: &Account) {
+    writer.start("Id");
+    match *account {
+        Account::IBANBelgianAccountNumber { ref number, .. } | Account::IBANForeignAccountNumber { ref number, .. } => {
+            writer.text("IBAN", number)
+        }
+        _ => {
+            writer.start("Othr");
+            writer.text("Id", account.number());
+            writer.end("Othr");
+        }
+    }
+    writer.end("Id");
+}
+
+fn write_agent(writer: &mut XmlWriter, version: CamtVersion, name: &str, bic: &str) {
+    writer.start(name);
+    writer.start("FinInstnId");
+    writer.text(version.bic_tag(), bic);
+    writer.end("FinInstnId");
+    writer.end(name);
+}
+
+fn write_balance(writer: &mut XmlWriter, code: &str, currency: &str, sign: &Sign, amount: u64, date: &NaiveDate) {
+    writer.start("Bal");
+    writer.start("Tp");
+    writer.start("CdOrPrtry");
+    writer.text("Cd", code);
+    writer.end("CdOrPrtry");
+    writer.end("Tp");
+    write_amount(writer, "Amt", currency, amount);
+    writer.text("CdtDbtInd", credit_debit(sign));
+    writer.start("Dt");
+    writer.text("Dt", &format_date(date));
+    writer.end("Dt");
+    writer.end("Bal");
+}
+
+fn write_related_parties(writer: &mut XmlWriter, version: CamtVersion, movement: &Movement) {
+    let name = movement
+        .counterparty_name
+        .as_ref()
+        .filter(|n| !n.is_empty());
+    if name.is_none() && movement.counterparty_account.is_none() {
+        return;
+    }
+    // The counterparty pays a credit and is paid by a debit
+    let (party, party_account) = match movement.sign() {
+        Sign::Credit => ("Dbtr", "DbtrAcct"),
+        Sign::Debit => ("Cdtr", "CdtrAcct"),
+    };
+    writer.start("RltdPties");
+    if let Some(name) = name {
+        writer.start(party);
+        if version == CamtVersion::V08 {
+            writer.start("Pty");
+            writer.text("Nm", name);
+            writer.end("Pty");
+        } else {
+            writer.text("Nm", name);
+        }
+        writer.end(party);
+    }
+    if let Some(ref account) = movement.counterparty_account {
+        writer.start(party_account);
+        write_account_id(writer, account);
+        writer.end(party_account);
+    }
+    writer.end("RltdPties");
+}
+
+fn write_transaction_details(writer: &mut XmlWriter, version: CamtVersion, currency: &str, movement: &Movement) {
+    writer.start("TxDtls");
+    writer.start("Refs");
+    writer.text("AcctSvcrRef", movement.bank_reference.trim());
+    if let Some(ref reference) = movement.customer_reference.as_ref().filter(|r| !r.is_empty()) {
+        writer.text("EndToEndId", reference);
+    }
+    writer.end("Refs");
+    if version == CamtVersion::V08 {
+        write_amount(writer, "Amt", currency, movement.unsigned_amount());
+        writer.text("CdtDbtInd", credit_debit(&movement.sign()));
+    } else {
+        writer.start("AmtDtls");
+        writer.start("TxAmt");
+        write_amount(writer, "Amt", currency, movement.unsigned_amount());
+        writer.end("TxAmt");
+        writer.end("AmtDtls");
+    }
+    write_related_parties(writer, version, movement);
+    if let Some(ref bic) = movement.counterparty_bic.as_ref().filter(|b| !b.is_empty()) {
+        let agent = match movement.sign() {
+            Sign::Credit => "DbtrAgt",
+            Sign::Debit => "CdtrAgt",
+        };
+        writer.start("RltdAgts");
+        write_agent(writer, version, agent, bic);
+        writer.end("RltdAgts");
+    }
+    if let Some(ref purpose) = movement.purpose {
+        writer.start("Purp");
+        writer.text("Cd", purpose.code());
+        writer.end("Purp");
+    }
+    if let Some(reference) = movement.structured_reference() {
+        writer.start("RmtInf");
+        writer.start("Strd");
+        writer.start("CdtrRefInf");
+        writer.start("Tp");
+        writer.start("CdOrPrtry");
+        writer.text("Cd", "SCOR");
+        writer.end("CdOrPrtry");
+        writer.text("Issr", "BBA");
+        writer.end("Tp");
+        writer.text("Ref", &reference);
+        writer.end("CdtrRefInf");
+        writer.end("Strd");
+        writer.end("RmtInf");
+    } else if !movement.communication.trim().is_empty() {
+        writer.start("RmtInf");
+        for chunk in text_chunks(&movement.communication) {
+            writer.text("Ustrd", &chunk);
+        }
+        writer.end("RmtInf");
+    }
+    if let Some(ref reason) = movement.r_reason {
+        writer.start("RtrInf");
+        writer.start("Rsn");
+        writer.text("Cd", reason.code());
+        writer.end("Rsn");
+        if let Some(ref r_transaction) = movement.r_transaction {
+            writer.text("AddtlInf", r_transaction.description());
+        }
+        writer.end("RtrInf");
+    }
+    writer.end("TxDtls");
+}
+
+// The detail records of an entry become its transaction details
+fn write_entry(writer: &mut XmlWriter, version: CamtVersion, currency: &str, movements: &[&Movement]) {
+    let movement = movements[0];
+    writer.start("Ntry");
+    writer.text("NtryRef", &movement.sequence);
+    write_amount(writer, "Amt", currency, movement.unsigned_amount());
+    writer.text("CdtDbtInd", credit_debit(&movement.sign()));
+    if version == CamtVersion::V08 {
+        writer.start("Sts");
+        writer.text("Cd", "BOOK");
+        writer.end("Sts");
+    } else {
+        writer.text("Sts", "BOOK");
+    }
+    writer.start("BookgDt");
+    writer.text("Dt", &format_date(&movement.entry_date));
+    writer.end("BookgDt");
+    writer.start("ValDt");
+    writer.text("Dt", &format_date(&movement.value_date));
+    writer.end("ValDt");
+    writer.text("AcctSvcrRef", movement.bank_reference.trim());
+    writer.start("BkTxCd");
+    writer.start("Prtry");
+    writer.text("Cd", &movement.transaction_code);
+    writer.text("Issr", "FEBELFIN");
+    writer.end("Prtry");
+    writer.end("BkTxCd");
+    writer.start("NtryDtls");
+    let details = if movements.len() > 1 { &movements[1..] } else { movements };
+    for detail in details {
+        write_transaction_details(writer, version, currency, detail);
+    }
+    writer.end("NtryDtls");
+    writer.end("Ntry");
+}
+
+fn write_statement(writer: &mut XmlWriter, version: CamtVersion, coda: &Coda) {
+    let header = &coda.header;
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+    let currency = old_balance.account.currency();
+
+    writer.start("Stmt");
+    writer.text("Id", &format!("{}-{}", header.file_reference.trim(), old_balance.old_sequence));
+    if let Ok(sequence) = old_balance.old_sequence.parse::<u32>() {
+        writer.text("ElctrncSeqNb", &sequence.to_string());
+    }
+    writer.text("CreDtTm", &format_date_time(&header.creation_date));
+    if header.duplicate {
+        writer.text("CpyDplctInd", "DUPL");
+    }
+    writer.start("Acct");
+    write_account_id(writer, &old_balance.account);
+    writer.text("Ccy", currency);
+    if !old_balance.account_description.is_empty() {
+        writer.text("Nm", &old_balance.account_description);
+    }
+    if !old_balance.account_holder_name.is_empty() {
+        writer.start("Ownr");
+        writer.text("Nm", &old_balance.account_holder_name);
+        writer.end("Ownr");
+    }
+    if !header.bic.is_empty() {
+        write_agent(writer, version, "Svcr", &header.bic);
+    }
+    writer.end("Acct");
+    write_balance(
+        writer,
+        "OPBD",
+        currency,
+        &old_balance.old_balance_sign,
+        old_balance.old_balance,
+        &old_balance.old_balance_date,
+    );
+    write_balance(
+        writer,
+        "CLBD",
+        currency,
+        &new_balance.new_balance_sign,
+        new_balance.new_balance,
+        &new_balance.new_balance_date,
+    );
+    for entry in coda.entries() {
+        write_entry(writer, version, currency, &entry);
+    }
+    writer.end("Stmt");
+}
+
+// All the files end up as statements of one BkToCstmrStmt message, the group header is taken from the first one
+pub fn to_camt053(coda_list: &[Coda], version: CamtVersion) -> String {
+    let mut writer = XmlWriter::new();
+    writer.declaration();
+    writer.start_attr("Document", "xmlns", version.namespace());
+    writer.start("BkToCstmrStmt");
+    writer.start("GrpHdr");
+    match coda_list.first() {
+        Some(coda) => {
+            let reference = coda.header.file_reference.trim();
+            let date = coda.header.creation_date;
+            writer.text("MsgId", &format!("{}-{}", date.format("%Y%m%d"), reference));
+            writer.text("CreDtTm", &format_date_time(&date));
+        }
+        None => {
+            writer.text("MsgId", "EMPTY");
+            writer.text("CreDtTm", "1970-01-01T00:00:00");
+        }
+    }
+    writer.end("GrpHdr");
+    for coda in coda_list {
+        write_statement(&mut writer, version, coda);
+    }
+    writer.end("BkToCstmrStmt");
+    writer.end("Document");
+    writer.output
+}
+
+#[cfg(test)]
+mod test_camt053 {
+    use std::env;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use coda::{CommunicationStructure, RTransaction};
+    use iso20022::ReturnReason;
+    use fixtures::{Document, child, children, parse_coda_list, path};
+
+    // The exports are checked by xmllint (libxml2-utils), XMLLINT names another binary
+    fn validate(xml: &str, version: CamtVersion) -> ::std::result::Result<(), String> {
+        let schema = match version {
+            CamtVersion::V02 => "schema/camt.053.001.02.xsd",
+            CamtVersion::V08 => "schema/camt.053.001.08.xsd",
+        };
+        let xmllint = env::var("XMLLINT").unwrap_or_else(|_| String::from("xmllint"));
+        let mut child = Command::new(&xmllint)
+            .args(&["--noout", "--schema", schema, "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("xmllint is needed to check the camt.053 exports against their schema");
+        child.stdin.take().unwrap().write_all(xml.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).into_owned())
+        }
+    }
+
+    #[test]
+    fn to_camt053_schema_valid() {
+        for version in [CamtVersion::V02, CamtVersion::V08] {
+            let xml = to_camt053(&parse_coda_list(), version);
+
+            assert_eq!(validate(&xml, version), Ok(()), "{:?} should match its schema", version);
+        }
+        let xml = to_camt053(&parse_coda_list(), CamtVersion::V02).replace("<Sts>BOOK</Sts>", "<Sts>DONE</Sts>");
+        assert_eq!(validate(&xml, CamtVersion::V02).is_err(), true, "Unknown entry status");
+        let xml = to_camt053(&parse_coda_list(), CamtVersion::V02);
+        assert_eq!(validate(&xml, CamtVersion::V08).is_err(), true, "001.02 is not 001.08");
+    }
+
+    #[test]
+    fn to_camt053_v02_valid() {
+        let xml = to_camt053(&parse_coda_list(), CamtVersion::V02);
+
+        let document = Document::parse(&xml);
+        assert_eq!(document.is_ok(), true, "Xml should be well formed");
+        let document = document.unwrap();
+        let root = document.root_element();
+        assert_eq!(root.tag_name().name(), "Document");
+        assert_eq!(root.tag_name().namespace(), Some("urn:iso:std:iso:20022:tech:xsd:camt.053.001.02"));
+        let statement = path(root, &["BkToCstmrStmt", "Stmt"]);
+        assert_eq!(path(statement, &["Acct", "Id", "Othr", "Id"]).text(), Some("435000000080"));
+        assert_eq!(path(statement, &["Acct", "Ccy"]).text(), Some("EUR"));
+        assert_eq!(path(statement, &["Acct", "Svcr", "FinInstnId", "BIC"]).text(), Some("KREDBEBB"));
+
+        let balances = children(statement, "Bal");
+        assert_eq!(balances.len(), 2, "There should be an opening and a closing balance");
+        assert_eq!(path(balances[0], &["Tp", "CdOrPrtry", "Cd"]).text(), Some("OPBD"));
+        assert_eq!(path(balances[1], &["Tp", "CdOrPrtry", "Cd"]).text(), Some("CLBD"));
+        assert_eq!(child(balances[1], "Amt").text(), Some("9405296.990"));
+        assert_eq!(child(balances[1], "Amt").attribute("Ccy"), Some("EUR"));
+        assert_eq!(child(balances[1], "CdtDbtInd").text(), Some("CRDT"));
+
+        let entries = children(statement, "Ntry");
+        let entry = entries[0];
+        assert_eq!(child(entry, "NtryRef").text(), Some("0001"));
+        assert_eq!(child(entry, "Amt").text(), Some("2578.250"));
+        assert_eq!(child(entry, "CdtDbtInd").text(), Some("DBIT"));
+        assert_eq!(child(entry, "Sts").text(), Some("BOOK"));
+        assert_eq!(path(entry, &["BookgDt", "Dt"]).text(), Some("2006-12-06"));
+        assert_eq!(path(entry, &["BkTxCd", "Prtry", "Cd"]).text(), Some("00799000"));
+        assert_eq!(
+            path(entry, &["NtryDtls", "TxDtls", "RmtInf", "Ustrd"]).text(),
+            Some("BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI ON 495953")
+        );
+    }
+
+    #[test]
+    fn to_camt053_v08_valid() {
+        let xml = to_camt053(&parse_coda_list(), CamtVersion::V08);
+
+        let document = Document::parse(&xml).unwrap();
+        let root = document.root_element();
+        assert_eq!(root.tag_name().namespace(), Some("urn:iso:std:iso:20022:tech:xsd:camt.053.001.08"));
+        let statement = path(root, &["BkToCstmrStmt", "Stmt"]);
+        assert_eq!(path(statement, &["Acct", "Svcr", "FinInstnId", "BICFI"]).text(), Some("KREDBEBB"));
+        let entry = child(statement, "Ntry");
+        assert_eq!(path(entry, &["Sts", "Cd"]).text(), Some("BOOK"));
+        assert_eq!(path(entry, &["NtryDtls", "TxDtls", "Amt"]).text(), Some("2578.250"));
+    }
+
+    #[test]
+    fn to_camt053_entry_details() {
+        let xml = to_camt053(&parse_coda_list(), CamtVersion::V02);
+
+        let document = Document::parse(&xml).unwrap();
+        let statement = path(document.root_element(), &["BkToCstmrStmt", "Stmt"]);
+        let entry = children(statement, "Ntry")
+            .into_iter()
+            .find(|n| child(*n, "NtryRef").text() == Some("0007"))
+            .unwrap();
+        let details = children(child(entry, "NtryDtls"), "TxDtls");
+        assert_eq!(details.len() > 1, true, "Globalised movement should have one TxDtls per detail");
+        let detail = details
+            .into_iter()
+            .find(|n| n.descendants().any(|d| d.text() == Some("068226750863")))
+            .unwrap();
+        assert_eq!(path(detail, &["RltdPties", "Cdtr", "Nm"]).text(), Some("T.P.F.  S.A."));
+    }
+
+    #[test]
+    fn to_camt053_structured_reference() {
+        let xml = to_camt053(&parse_coda_list(), CamtVersion::V02);
+
+        let document = Document::parse(&xml).unwrap();
+        let statement = path(document.root_element(), &["BkToCstmrStmt", "Stmt"]);
+        let entry = children(statement, "Ntry")
+            .into_iter()
+            .find(|n| child(*n, "NtryRef").text() == Some("0053"))
+            .unwrap();
+        let reference = path(entry, &["NtryDtls", "TxDtls", "RmtInf", "Strd", "CdtrRefInf"]);
+        assert_eq!(path(reference, &["Tp", "CdOrPrtry", "Cd"]).text(), Some("SCOR"));
+        assert_eq!(path(reference, &["Tp", "Issr"]).text(), Some("BBA"));
+        assert_eq!(child(reference, "Ref").text(), Some("269021157996"));
+    }
+
+    #[test]
+    fn to_camt053_return_reason() {
+        let mut coda_list = parse_coda_list();
+        {
+            let movement = &mut coda_list[0].movements[0];
+            movement.r_transaction = Some(RTransaction::Return);
+            movement.r_reason = Some(ReturnReason::RefundRequestByEndCustomer);
+            movement.communication_structure = CommunicationStructure::Unstructured;
+            movement.communication = String::from("R&D <RETURN>");
+        }
+
+        let xml = to_camt053(&coda_list, CamtVersion::V02);
+
+        assert_eq!(validate(&xml, CamtVersion::V02), Ok(()));
+        assert_eq!(validate(&to_camt053(&coda_list, CamtVersion::V08), CamtVersion::V08), Ok(()));
+        let document = Document::parse(&xml).unwrap();
+        let detail = path(
+            document.root_element(),
+            &["BkToCstmrStmt", "Stmt", "Ntry", "NtryDtls", "TxDtls"],
+        );
+        assert_eq!(path(detail, &["RtrInf", "Rsn", "Cd"]).text(), Some("MD06"));
+        assert_eq!(path(detail, &["RmtInf", "Ustrd"]).text(), Some("R&D <RETURN>"));
+    }
+
+    #[test]
+    fn camt_version_from_str() {
+        assert_eq!("001.02".parse::<CamtVersion>(), Ok(CamtVersion::V02));
+        assert_eq!("08".parse::<CamtVersion>(), Ok(CamtVersion::V08));
+        assert_eq!("001.04".parse::<CamtVersion>().is_err(), true, "001.04 should not be ok");
+    }
+
+    #[test]
+    fn text_chunks_valid() {
+        let text = "A".repeat(150);
+
+        let actual = text_chunks(&text);
+
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].len(), 140);
+        assert_eq!(text_chunks("LINE 1\nLINE 2"), vec!["LINE 1 LINE 2"]);
+    }
+}
@@ -6,17 +6,23 @@ extern crate serde_json;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Cursor, Read};
 
+use coda::serde::ser::SerializeStruct;
+use coda::serde::{Serialize, Serializer};
+
 use chrono::NaiveDate;
 
 use coda::encoding::label::encoding_from_whatwg_label;
 use coda::encoding::DecoderTrap;
-use json::date_serde;
+use json::{amount_serde, date_serde, JsonDate, SignedAmount};
+use iso20022::{CategoryPurpose, Purpose, ReturnReason};
+use schema::SCHEMA_VERSION;
 
 use errors::*;
-use utils::{parse_date, parse_duplicate, parse_field, parse_sign, parse_str, parse_str_append, parse_str_trim, Sign,
-            StringUtils, parse_u32, parse_u64, parse_u8};
+use utils::{parse_code, parse_date, parse_duplicate, parse_field, parse_sign, parse_str, parse_str_append, parse_str_trim,
+            signed_amount, Sign, StringUtils, parse_u32, parse_u64, parse_u8};
 
-#[derive(PartialEq, Debug, Serialize)]
+// Serialized by the json module, along the account tagging of the json shape
+#[derive(PartialEq, Debug, Deserialize)]
 pub enum Account {
     BelgianAccountNumber {
         number: String,
@@ -37,12 +43,53 @@ pub enum Account {
     },
 }
 
-#[derive(PartialEq, Debug, Serialize)]
+impl Account {
+    pub fn number(&self) -> &str {
+        match *self {
+            Account::BelgianAccountNumber { ref number, .. }
+            | Account::ForeignAccountNumber { ref number, .. }
+            | Account::IBANBelgianAccountNumber { ref number, .. }
+            | Account::IBANForeignAccountNumber { ref number, .. } => number,
+        }
+    }
+
+    pub fn currency(&self) -> &str {
+        match *self {
+            Account::BelgianAccountNumber { ref currency, .. }
+            | Account::ForeignAccountNumber { ref currency, .. }
+            | Account::IBANBelgianAccountNumber { ref currency, .. }
+            | Account::IBANForeignAccountNumber { ref currency, .. } => currency,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub enum CommunicationStructure {
     Structured,
     Unstructured,
 }
 
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum RTransaction {
+    Reject,
+    Return,
+    Refund,
+    Reversal,
+    Cancellation,
+}
+
+impl RTransaction {
+    pub fn description(&self) -> &str {
+        match *self {
+            RTransaction::Reject => "Reject: refused before settlement",
+            RTransaction::Return => "Return: refused after settlement",
+            RTransaction::Refund => "Refund: claimed back by the debtor",
+            RTransaction::Reversal => "Reversal: reimbursed by the creditor",
+            RTransaction::Cancellation => "Cancellation: recalled before settlement",
+        }
+    }
+}
+
 fn parse_account(s: &str) -> Result<Account> {
     match s.get(0..1).unwrap() {
         "0" => Ok(Account::BelgianAccountNumber {
@@ -66,6 +113,50 @@ fn parse_account(s: &str) -> Result<Account> {
     }
 }
 
+fn parse_counterparty_account(s: &str) -> Result<Option<Account>> {
+    let s = s.to_string();
+    let number = s.get_range(0..34);
+    let number = number.trim();
+    if number.is_empty() || number.chars().all(|c| c == '0') {
+        return Ok(None);
+    }
+    let is_iban = number.len() > 4 && number.chars().take(2).all(|c| c.is_ascii_uppercase())
+        && number.chars().skip(2).take(2).all(|c| c.is_ascii_digit());
+    let is_belgian = s.get_range(0..12).chars().all(|c| c.is_ascii_digit()) && s.get_range(12..13) == " ";
+    if is_iban {
+        let number = String::from(number);
+        let currency = String::from(s.get_range(34..37).trim());
+        if number.starts_with("BE") {
+            Ok(Some(Account::IBANBelgianAccountNumber { number, currency }))
+        } else {
+            Ok(Some(Account::IBANForeignAccountNumber { number, currency }))
+        }
+    } else if is_belgian {
+        Ok(Some(Account::BelgianAccountNumber {
+            number: s.get_range(0..12),
+            currency: String::from(s.get_range(13..16).trim()),
+            country: String::from("BE"),
+        }))
+    } else {
+        Ok(Some(Account::ForeignAccountNumber {
+            number: String::from(number),
+            currency: String::from(s.get_range(34..37).trim()),
+        }))
+    }
+}
+
+// Files following older versions of the standard use this zone for other data, anything else than 1-5 is ignored
+fn parse_rtransaction(s: &str) -> Result<Option<RTransaction>> {
+    match s {
+        "1" => Ok(Some(RTransaction::Reject)),
+        "2" => Ok(Some(RTransaction::Return)),
+        "3" => Ok(Some(RTransaction::Refund)),
+        "4" => Ok(Some(RTransaction::Reversal)),
+        "5" => Ok(Some(RTransaction::Cancellation)),
+        _ => Ok(None),
+    }
+}
+
 fn parse_communicationstructure(s: &str) -> Result<CommunicationStructure> {
     match s {
         "0" => Ok(CommunicationStructure::Unstructured),
@@ -74,8 +165,20 @@ fn parse_communicationstructure(s: &str) -> Result<CommunicationStructure> {
     }
 }
 
-#[derive(Debug, Serialize)]
+// The encoding labels are the whatwg ones, checked with the options so a wrong label stops before any file is read
+pub fn is_encoding_label(label: &str) -> bool {
+    encoding_from_whatwg_label(label).is_some()
+}
+
+fn schema_version() -> u32 {
+    SCHEMA_VERSION
+}
+
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Coda {
+    // Documents written before the schema was versioned have no schema_version
+    #[serde(default = "schema_version")]
+    pub schema_version: u32,
     pub header: Header,
     pub old_balance: OldBalance,
     pub movements: Vec<Movement>,
@@ -85,7 +188,7 @@ pub struct Coda {
     pub trailer: Trailer,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Header {
     #[serde(with = "date_serde")] pub creation_date: NaiveDate,
     pub bank_id: String,
@@ -99,7 +202,7 @@ pub struct Header {
     pub version: u8,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Deserialize)]
 pub struct OldBalance {
     pub account: Account,     // ': (slice(1, 2), str),
     pub old_sequence: String, // ': (slice(2, 5), str),
@@ -112,14 +215,16 @@ pub struct OldBalance {
     pub coda_sequence: String,                                     // ': (slice(125, 128), str),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Movement {
     pub sequence: String,                                    //': (slice(2, 6), str),
     pub detail_sequence: String,                             //': (slice(6, 10), str),
     pub bank_reference: String,                              //': (slice(10, 31), str),
+    #[serde(serialize_with = "amount_serde::serialize_movement")]
     pub amount: u64,                                         //': (slice(31, 47), _amount),
     #[serde(with = "date_serde")] pub value_date: NaiveDate, //': (slice(47, 53), _date),
     pub transaction_code: String,                            //': (slice(53, 61), str),
+    pub communication_structure: CommunicationStructure,     //': (slice(61, 62), str),
     pub communication: String,                               //': (slice(61, 115), str),
     #[serde(with = "date_serde")] pub entry_date: NaiveDate, //': (slice(115, 121), _date),
     pub statement_number: String,                            //': (slice(121, 124), str),
@@ -127,16 +232,19 @@ pub struct Movement {
     //pub _communication: String,     //': (slice(10, 63), str),
     pub customer_reference: Option<String>, //': (slice(63, 98), _string),
     pub counterparty_bic: Option<String>,   //': (slice(98, 109), _string),
-    pub r_transaction: Option<String>,      //': (slice(112, 113), _string),
-    pub r_reason: Option<String>,           //': (slice(113, 117), _string),
-    pub category_purpose: Option<String>,   //': (slice(117, 121), _string),
-    pub purpose: Option<String>,            //': (slice(121, 125), _string),
+    pub r_transaction: Option<RTransaction>, //': (slice(112, 113), _string),
+    pub r_reason: Option<ReturnReason>,      //': (slice(113, 117), _string),
+    pub category_purpose: Option<CategoryPurpose>, //': (slice(117, 121), _string),
+    pub purpose: Option<Purpose>,                  //': (slice(121, 125), _string),
     // type 3
-    pub counterparty_account: Option<String>, //': (slice(10, 47), _string),
-    pub counterparty_name: Option<String>,    //': (slice(47, 82), _string),
+    pub counterparty_account: Option<Account>, //': (slice(10, 47), _string),
+    pub counterparty_name: Option<String>,     //': (slice(47, 82), _string),
+    // continuation codes of the last record (2.1, 2.2 or 2.3) of the movement
+    pub next_code: bool, //': (slice(125, 126), _code),
+    pub link_code: bool, //': (slice(127, 128), _code),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Information {
     pub sequence: String,         //': (slice(2, 6), str),
     pub detail_sequence: String,  //': (slice(6, 10), str),
@@ -146,27 +254,62 @@ pub struct Information {
     pub communication: String, //': (slice(39, 113), str),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct FreeCommunication {
     pub sequence: String,        //': (slice(2, 6), str),
     pub detail_sequence: String, //': (slice(6, 10), str),
     pub text: String,            //': (slice(32, 112), str),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Deserialize)]
 pub struct NewBalance {
     pub new_sequence: String, //': (slice(1, 4), str),
     // We don't store the account coming from the new balance
     pub new_balance_sign: Sign,
-    pub new_balance: u64,                                          //': (slice(41, 57), _amount),
+    pub new_balance: u64,                                          //': (slice(42, 57), _amount),
     #[serde(with = "date_serde")] pub new_balance_date: NaiveDate, //': (slice(57, 63), _date),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct Trailer {
     pub number_records: u32, //': (slice(16, 22), int),
-    pub total_debit: u64,    //': (slice(22, 37), _amount),
-    pub total_credit: u64,   //': (slice(37, 52), _amount),
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_debit: u64, //': (slice(22, 37), _amount),
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_credit: u64, //': (slice(37, 52), _amount),
+}
+
+// The balances are written with their sign, so the json shape can write them as signed decimals
+impl Serialize for OldBalance {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut fields = s.serialize_struct("OldBalance", 8)?;
+        fields.serialize_field("account", &self.account)?;
+        fields.serialize_field("old_sequence", &self.old_sequence)?;
+        fields.serialize_field("old_balance_sign", &self.old_balance_sign)?;
+        fields.serialize_field("old_balance", &SignedAmount(&self.old_balance_sign, self.old_balance))?;
+        fields.serialize_field("old_balance_date", &JsonDate(&self.old_balance_date))?;
+        fields.serialize_field("account_holder_name", &self.account_holder_name)?;
+        fields.serialize_field("account_description", &self.account_description)?;
+        fields.serialize_field("coda_sequence", &self.coda_sequence)?;
+        fields.end()
+    }
+}
+
+impl Serialize for NewBalance {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut fields = s.serialize_struct("NewBalance", 4)?;
+        fields.serialize_field("new_sequence", &self.new_sequence)?;
+        fields.serialize_field("new_balance_sign", &self.new_balance_sign)?;
+        fields.serialize_field("new_balance", &SignedAmount(&self.new_balance_sign, self.new_balance))?;
+        fields.serialize_field("new_balance_date", &JsonDate(&self.new_balance_date))?;
+        fields.end()
+    }
 }
 
 impl Trailer {
@@ -214,7 +357,41 @@ impl Header {
     }
 }
 
+// The first digit of the amount zone of a movement is the sign, followed by 15 digits with 3 decimals
+pub fn split_movement_amount(amount: u64) -> (Sign, u64) {
+    if amount >= 1_000_000_000_000_000 {
+        (Sign::Debit, amount % 1_000_000_000_000_000)
+    } else {
+        (Sign::Credit, amount)
+    }
+}
+
 impl Movement {
+    pub fn sign(&self) -> Sign {
+        split_movement_amount(self.amount).0
+    }
+
+    pub fn unsigned_amount(&self) -> u64 {
+        split_movement_amount(self.amount).1
+    }
+
+    pub fn signed_amount(&self) -> i64 {
+        signed_amount(&self.sign(), self.unsigned_amount())
+    }
+
+    // Structured communication of type 101 is the Belgian structured creditor reference (OGM), 12 digits
+    pub fn structured_reference(&self) -> Option<String> {
+        if self.communication_structure != CommunicationStructure::Structured || !self.communication.starts_with("101") {
+            return None;
+        }
+        let reference = self.communication.to_string().get_range(3..15);
+        if reference.len() == 12 && reference.chars().all(|c| c.is_ascii_digit()) {
+            Some(reference)
+        } else {
+            None
+        }
+    }
+
     fn parse_type1(line: &str) -> Result<Movement> {
         Ok(Movement {
             sequence: parse_field(line, 2..6, parse_str).chain_err(|| "Could not parse sequence")?,
@@ -223,6 +400,8 @@ impl Movement {
             amount: parse_field(line, 31..47, parse_u64).chain_err(|| "Could not parse amount")?,
             value_date: parse_field(line, 47..53, parse_date).chain_err(|| "Could not parse value_date")?,
             transaction_code: parse_field(line, 53..61, parse_str).chain_err(|| "Could not parse transaction_code")?,
+            communication_structure: parse_field(line, 61..62, parse_communicationstructure)
+                .chain_err(|| "Could not parse communication_structure")?,
             communication: parse_field(line, 62..115, parse_str_trim).chain_err(|| "Could not parse transaction_code")?,
             entry_date: parse_field(line, 115..121, parse_date).chain_err(|| "Could not parse entry_date")?,
             statement_number: parse_field(line, 121..124, parse_str).chain_err(|| "Could not parse statement_number")?,
@@ -234,37 +413,46 @@ impl Movement {
             purpose: None,
             counterparty_account: None,
             counterparty_name: None,
+            next_code: parse_field(line, 125..126, parse_code).chain_err(|| "Could not parse next_code")?,
+            link_code: parse_field(line, 127..128, parse_code).chain_err(|| "Could not parse link_code")?,
         })
     }
 
+    fn parse_continuation(&mut self, line: &str) -> Result<()> {
+        self.next_code = parse_field(line, 125..126, parse_code).chain_err(|| "Could not parse next_code")?;
+        self.link_code = parse_field(line, 127..128, parse_code).chain_err(|| "Could not parse link_code")?;
+
+        Ok(())
+    }
+
     pub fn parse_type2(&mut self, line: &str) -> Result<()> {
         self.customer_reference =
-            Some(parse_field(line, 121..124, parse_str_trim).chain_err(|| "Could not parse customer_reference")?);
+            Some(parse_field(line, 63..98, parse_str_trim).chain_err(|| "Could not parse customer_reference")?);
         self.counterparty_bic =
             Some(parse_field(line, 98..109, parse_str_trim).chain_err(|| "Could not parse counterparty_bic")?);
         self.r_transaction =
-            Some(parse_field(line, 112..113, parse_str_trim).chain_err(|| "Could not parse r_transaction")?);
-        self.r_reason = Some(parse_field(line, 113..117, parse_str_trim).chain_err(|| "Could not parse r_reason")?);
+            parse_field(line, 112..113, parse_rtransaction).chain_err(|| "Could not parse r_transaction")?;
+        self.r_reason = parse_field(line, 113..117, ReturnReason::parse).chain_err(|| "Could not parse r_reason")?;
         self.category_purpose =
-            Some(parse_field(line, 117..121, parse_str_trim).chain_err(|| "Could not parse category_purpose")?);
-        self.purpose = Some(parse_field(line, 121..125, parse_str_trim).chain_err(|| "Could not parse purpose")?);
+            parse_field(line, 117..121, CategoryPurpose::parse).chain_err(|| "Could not parse category_purpose")?;
+        self.purpose = parse_field(line, 121..125, Purpose::parse).chain_err(|| "Could not parse purpose")?;
 
         let communication = parse_field(line, 10..63, parse_str_append).chain_err(|| "Could not parse communication")?;
         self.communication.push_str(&communication);
 
-        Ok(())
+        self.parse_continuation(line)
     }
 
     pub fn parse_type3(&mut self, line: &str) -> Result<()> {
-        self.counterparty_name =
-            Some(parse_field(line, 10..47, parse_str_trim).chain_err(|| "Could not parse counterparty_name")?);
         self.counterparty_account =
-            Some(parse_field(line, 47..82, parse_str_trim).chain_err(|| "Could not parse counterparty_account")?);
+            parse_field(line, 10..47, parse_counterparty_account).chain_err(|| "Could not parse counterparty_account")?;
+        self.counterparty_name =
+            Some(parse_field(line, 47..82, parse_str_trim).chain_err(|| "Could not parse counterparty_name")?);
 
         let communication = parse_field(line, 82..125, parse_str_append).chain_err(|| "Could not parse communication")?;
         self.communication.push_str(&communication);
 
-        Ok(())
+        self.parse_continuation(line)
     }
 }
 
@@ -317,19 +505,60 @@ impl NewBalance {
     fn parse(line: &str) -> Result<NewBalance> {
         Ok(NewBalance {
             new_sequence: parse_field(line, 1..4, parse_str).chain_err(|| "Could not parse new_sequence")?,
-            new_balance_sign: parse_field(line, 42..43, parse_sign).chain_err(|| "Could not parse old_balance_sign")?,
-            new_balance: parse_field(line, 41..57, parse_u64).chain_err(|| "Could not parse new_balance")?,
+            new_balance_sign: parse_field(line, 41..42, parse_sign).chain_err(|| "Could not parse new_balance_sign")?,
+            new_balance: parse_field(line, 42..57, parse_u64).chain_err(|| "Could not parse new_balance")?,
             new_balance_date: parse_field(line, 57..63, parse_date).chain_err(|| "Could not parse new_balance_date")?,
         })
     }
 }
 
 impl Coda {
+    // Movements grouped per sequence number: the booked entry first, followed by its detail records
+    pub fn entries(&self) -> Vec<Vec<&Movement>> {
+        let mut entries: Vec<Vec<&Movement>> = Vec::new();
+        for movement in &self.movements {
+            let same_entry = entries
+                .last()
+                .map_or(false, |entry| entry[0].sequence == movement.sequence);
+            if same_entry {
+                entries.last_mut().unwrap().push(movement);
+            } else {
+                entries.push(vec![movement]);
+            }
+        }
+        entries
+    }
+
+    // Debit and credit totals of the booked entries, the details are not counted as in the trailer totals
+    pub fn entry_totals(&self) -> (u64, u64) {
+        self.entries()
+            .iter()
+            .fold((0, 0), |(debit, credit), entry| match entry[0].sign() {
+                Sign::Debit => (debit + entry[0].unsigned_amount(), credit),
+                Sign::Credit => (debit, credit + entry[0].unsigned_amount()),
+            })
+    }
+
+    // A statement left with part of its entries (ie by a filter) closes on its old balance plus the kept entries
+    pub fn rebase_new_balance(&mut self) {
+        let (debit, credit) = self.entry_totals();
+        let opening = signed_amount(&self.old_balance.old_balance_sign, self.old_balance.old_balance);
+        let closing = opening + credit as i64 - debit as i64;
+        self.new_balance.new_balance_sign = if closing < 0 { Sign::Debit } else { Sign::Credit };
+        self.new_balance.new_balance = closing.unsigned_abs();
+    }
+
+    // The counterparty of an entry is on the entry itself or, for globalised entries, on its first detail having one
+    pub fn entry_counterparty<'a>(entry: &[&'a Movement]) -> Option<&'a Movement> {
+        entry
+            .iter()
+            .find(|m| m.counterparty_name.as_ref().map_or(false, |n| !n.is_empty()) || m.counterparty_account.is_some())
+            .cloned()
+    }
+
     pub fn parse(coda_filename: &str, encoding_label: &str) -> Result<Coda> {
         let f = File::open(coda_filename).chain_err(|| format!("Unable to open {}", coda_filename))?;
 
-        let encoding = encoding_from_whatwg_label(encoding_label).unwrap();
-
         let mut reader = BufReader::new(f);
         let mut buf = Vec::new();
 
@@ -337,7 +566,18 @@ impl Coda {
             .read_to_end(&mut buf)
             .chain_err(|| "Error reading into buffer")?;
 
-        let decoded = encoding.decode(&buf, DecoderTrap::Strict).unwrap();
+        Coda::parse_bytes(&buf, encoding_label)
+    }
+
+    // Parses the content of a coda file read elsewhere, ie from an archive
+    pub fn parse_bytes(buf: &[u8], encoding_label: &str) -> Result<Coda> {
+        let encoding = encoding_from_whatwg_label(encoding_label)
+            .ok_or_else(|| Error::from(format!("Unknown encoding [{}]", encoding_label)))?;
+
+        let decoded = encoding
+            .decode(buf, DecoderTrap::Strict)
+            .map_err(|e| Error::from(e.into_owned()))
+            .chain_err(|| format!("Could not decode the content as {}", encoding.name()))?;
         let cursor = Cursor::new(decoded);
 
         let mut header: Option<Header> = None;
@@ -348,7 +588,7 @@ impl Coda {
         let mut informations: Vec<Information> = Vec::new();
         let mut free_communications: Vec<FreeCommunication> = Vec::new();
         for (num, line) in cursor.lines().enumerate() {
-            let line = line.unwrap();
+            let line = line.chain_err(|| format!("Could not read line {}", num + 1))?;
             match line.get_range(0..1).as_str() {
                 "0" => {
                     header = Some(Header::parse(&line).chain_err(|| -> Error { "Could not parse header".into() })?);
@@ -427,6 +667,7 @@ impl Coda {
             && trailer.is_some()
         {
             Ok(Coda {
+                schema_version: SCHEMA_VERSION,
                 header: header.unwrap(),
                 old_balance: old_balance.unwrap(),
                 movements: movements,
@@ -458,6 +699,44 @@ mod test_parse_coda {
 
         assert_eq!(coda.is_ok(), false, "CODA-bad.txt should not be ok");
     }
+
+    #[test]
+    fn coda_entries_valid() {
+        let coda = Coda::parse("test-data/CODA.txt", "latin1").unwrap();
+
+        let entries = coda.entries();
+
+        assert_eq!(entries.iter().map(|e| e.len()).sum::<usize>(), coda.movements.len());
+        assert_eq!(entries[0][0].sequence, "0001");
+        let entry = entries.iter().find(|e| e[0].sequence == "0007").unwrap();
+        assert_eq!(entry.len() > 1, true, "Movement 0007 should have detail records");
+        assert_eq!(entry.iter().all(|m| m.sequence == "0007"), true, "Details should share the sequence");
+    }
+
+    #[test]
+    fn coda_entry_totals_match_trailer() {
+        let coda = Coda::parse("test-data/CODA.txt", "latin1").unwrap();
+
+        let (debit, credit) = coda.entry_totals();
+
+        assert_eq!(debit, coda.trailer.total_debit, "Debit total should match the trailer");
+        assert_eq!(credit, coda.trailer.total_credit, "Credit total should match the trailer");
+    }
+
+    #[test]
+    fn coda_rebase_new_balance() {
+        let mut coda = Coda::parse("test-data/CODA.txt", "latin1").unwrap();
+        let new_balance = coda.new_balance.new_balance;
+
+        coda.rebase_new_balance();
+        assert_eq!(coda.new_balance.new_balance, new_balance, "All the entries give the closing balance");
+
+        // Entry 0001 is a debit of 2578.250 on an opening balance of 0
+        coda.movements.retain(|m| m.sequence == "0001");
+        coda.rebase_new_balance();
+        assert_eq!(coda.new_balance.new_balance_sign, Sign::Debit);
+        assert_eq!(coda.new_balance.new_balance, 2578250);
+    }
 }
 
 #[cfg(test)]
@@ -637,6 +916,106 @@ mod test_parse_account {
     }
 }
 
+#[cfg(test)]
+mod test_parse_rtransaction {
+    use super::RTransaction;
+    use super::parse_rtransaction;
+
+    #[test]
+    fn parse_rtransaction_valid() {
+        assert_eq!(parse_rtransaction("1").unwrap(), Some(RTransaction::Reject));
+        assert_eq!(parse_rtransaction("2").unwrap(), Some(RTransaction::Return));
+        assert_eq!(parse_rtransaction("3").unwrap(), Some(RTransaction::Refund));
+        assert_eq!(parse_rtransaction("4").unwrap(), Some(RTransaction::Reversal));
+        assert_eq!(parse_rtransaction("5").unwrap(), Some(RTransaction::Cancellation));
+    }
+
+    #[test]
+    fn parse_rtransaction_blank() {
+        let actual = parse_rtransaction(" ");
+        assert_eq!(actual.is_ok(), true, "' ' should be ok");
+        assert_eq!(actual.unwrap(), None, "' ' should be None");
+    }
+
+    #[test]
+    fn parse_rtransaction_unknown() {
+        let actual = parse_rtransaction("7");
+        assert_eq!(actual.is_ok(), true, "'7' should be ok");
+        assert_eq!(actual.unwrap(), None, "'7' should be None");
+    }
+}
+
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod test_parse_counterparty_account {
+    use super::Account;
+    use super::parse_counterparty_account;
+
+    #[test]
+    fn parse_counterparty_account_valid_BelgianAccountNumber() {
+        let actual = parse_counterparty_account("191038188256 EUR                     ");
+        assert_eq!(actual.is_ok(), true, "Belgian account should be ok");
+        assert_eq!(
+            actual.unwrap(),
+            Some(Account::BelgianAccountNumber {
+                number: String::from("191038188256"),
+                currency: String::from("EUR"),
+                country: String::from("BE"),
+            }),
+            "'191038188256' should be BelgianAccountNumber"
+        );
+    }
+
+    #[test]
+    fn parse_counterparty_account_valid_IBANBelgianAccountNumber() {
+        let actual = parse_counterparty_account("BE68539007547034                  EUR");
+        assert_eq!(actual.is_ok(), true, "Belgian IBAN should be ok");
+        assert_eq!(
+            actual.unwrap(),
+            Some(Account::IBANBelgianAccountNumber {
+                number: String::from("BE68539007547034"),
+                currency: String::from("EUR"),
+            }),
+            "'BE68539007547034' should be IBANBelgianAccountNumber"
+        );
+    }
+
+    #[test]
+    fn parse_counterparty_account_valid_IBANForeignAccountNumber() {
+        let actual = parse_counterparty_account("NL34KRED0633083542                   ");
+        assert_eq!(actual.is_ok(), true, "Foreign IBAN should be ok");
+        assert_eq!(
+            actual.unwrap(),
+            Some(Account::IBANForeignAccountNumber {
+                number: String::from("NL34KRED0633083542"),
+                currency: String::from(""),
+            }),
+            "'NL34KRED0633083542' should be IBANForeignAccountNumber"
+        );
+    }
+
+    #[test]
+    fn parse_counterparty_account_valid_ForeignAccountNumber() {
+        let actual = parse_counterparty_account("12-3456-789                       USD");
+        assert_eq!(actual.is_ok(), true, "Foreign account should be ok");
+        assert_eq!(
+            actual.unwrap(),
+            Some(Account::ForeignAccountNumber {
+                number: String::from("12-3456-789"),
+                currency: String::from("USD"),
+            }),
+            "'12-3456-789' should be ForeignAccountNumber"
+        );
+    }
+
+    #[test]
+    fn parse_counterparty_account_valid_blank() {
+        let actual = parse_counterparty_account("000000000000                         ");
+        assert_eq!(actual.is_ok(), true, "Blank account should be ok");
+        assert_eq!(actual.unwrap(), None, "'000000000000' should be None");
+    }
+}
+
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod test_parse_newbalance {
@@ -670,6 +1049,28 @@ mod test_parse_newbalance {
             "new_balance_date should be 07/12/2006"
         );
     }
+
+    #[test]
+    fn parse_newbalance_debit() {
+        let line = "8001435000000080 EUR0BE                  1000009405296990071206                                                                0";
+
+        let actual = NewBalance::parse(line);
+
+        assert_eq!(actual.is_ok(), true, "NewBalance shoud be ok");
+        let actual = actual.unwrap();
+        assert_eq!(actual.new_balance_sign, Sign::Debit, "new_balance_sign should be 'Debit'");
+        assert_eq!(actual.new_balance, 9405296990, "new_balance should be '9405296990'");
+    }
+
+    #[test]
+    fn parse_newbalance_sign_zone() {
+        // The sign is in position 42, before the 15 digits of the amount
+        let line = "8001435000000080 EUR0BE                  2000009405296990071206                                                                0";
+
+        let actual = NewBalance::parse(line);
+
+        assert_eq!(actual.is_ok(), false, "Sign '2' should not be ok");
+    }
 }
 
 #[cfg(test)]
@@ -703,7 +1104,12 @@ mod test_parse_movement {
 
     use chrono::NaiveDate;
 
+    use super::Account;
+    use super::CommunicationStructure;
     use super::Movement;
+    use super::RTransaction;
+    use iso20022::{CategoryPurpose, Purpose, ReturnReason};
+    use utils::Sign;
 
     #[test]
     fn parse_movement_type1_valid() {
@@ -745,6 +1151,9 @@ mod test_parse_movement {
         );
         assert_eq!(actual.entry_date, NaiveDate::from_ymd(2006, 12, 6));
         assert_eq!(actual.statement_number, "001");
+        assert_eq!(actual.sign(), Sign::Debit, "sign should be Debit");
+        assert_eq!(actual.unsigned_amount(), 2578250, "unsigned_amount should be 2578250");
+        assert_eq!(actual.signed_amount(), -2578250, "signed_amount should be -2578250");
     }
 
     #[test]
@@ -814,22 +1223,54 @@ mod test_parse_movement {
             "",
             "counterparty_bic should be ''"
         );
+        assert_eq!(actual.r_transaction, None, "r_transaction should be None");
+        assert_eq!(actual.r_reason, None, "r_reason should be None");
+        assert_eq!(actual.category_purpose, None, "category_purpose should be None");
+        assert_eq!(actual.purpose, None, "purpose should be None");
+
         assert_eq!(
-            actual.r_transaction.unwrap(),
-            "",
-            "r_transaction should be ''"
+            actual.communication,
+            "BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D\'OPERATI\nON 495953"
+        );
+    }
+
+    #[test]
+    fn parse_movement_type2_purpose_valid() {
+        let line1 = "2100010000EPIB00048 AWIUBTKAPUO1000000002578250061206007990000BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI06120600111 0";
+        let line2 = "2200010000ON 495953                                                                               GKCCBEBB           SUPPGDDS0 0";
+
+        let actual = Movement::parse_type1(line1);
+        let mut actual = actual.unwrap();
+        let result = actual.parse_type2(line2);
+
+        assert_eq!(result.is_ok(), true);
+        assert_eq!(
+            actual.category_purpose,
+            Some(CategoryPurpose::SupplierPayment),
+            "category_purpose should be SupplierPayment"
         );
-        assert_eq!(actual.r_reason.unwrap(), "", "r_reason should be ''");
         assert_eq!(
-            actual.category_purpose.unwrap(),
-            "",
-            "category_purpose should be ''"
+            actual.purpose,
+            Some(Purpose::PurchaseSaleOfGoods),
+            "purpose should be PurchaseSaleOfGoods"
         );
-        assert_eq!(actual.purpose.unwrap(), "", "purpose should be ''");
+    }
+
+    #[test]
+    fn parse_movement_type2_rtransaction_valid() {
+        let line1 = "2100010000EPIB00048 AWIUBTKAPUO1000000002578250061206007990000BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI06120600111 0";
+        let line2 = "2200010000ON 495953                                                                               GKCCBEBB      2AM04        0 0";
 
+        let actual = Movement::parse_type1(line1);
+        let mut actual = actual.unwrap();
+        let result = actual.parse_type2(line2);
+
+        assert_eq!(result.is_ok(), true);
+        assert_eq!(actual.r_transaction, Some(RTransaction::Return), "r_transaction should be Return");
         assert_eq!(
-            actual.communication,
-            "BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D\'OPERATI\nON 495953"
+            actual.r_reason,
+            Some(ReturnReason::InsufficientFunds),
+            "r_reason should be InsufficientFunds"
         );
     }
 
@@ -845,14 +1286,18 @@ mod test_parse_movement {
         assert_eq!(result.is_ok(), true);
 
         assert_eq!(
-            actual.counterparty_name.unwrap(),
-            "068226750863",
-            "counterparty_name should be '068226750863'"
+            actual.counterparty_account.unwrap(),
+            Account::BelgianAccountNumber {
+                number: String::from("068226750863"),
+                currency: String::from(""),
+                country: String::from("BE"),
+            },
+            "counterparty_account should be '068226750863'"
         );
         assert_eq!(
-            actual.counterparty_account.unwrap(),
+            actual.counterparty_name.unwrap(),
             "T.P.F.  S.A.",
-            "counterparty_account should be 'T.P.F.  S.A.'"
+            "counterparty_name should be 'T.P.F.  S.A.'"
         );
 
         assert_eq!(
@@ -860,6 +1305,172 @@ mod test_parse_movement {
             "BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D\'OPERATI\n"
         );
     }
+
+    #[test]
+    fn parse_movement_type1_communication_structure() {
+        let structured = "2100530000IKKUZ0AAAAOVSBBNONTVA0000000000817560061206001500001101269021157996                                      06120600101 0";
+        let unstructured = "2100010000EPIB00048 AWIUBTKAPUO1000000002578250061206007990000BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI06120600111 0";
+
+        let actual = Movement::parse_type1(structured).unwrap();
+        assert_eq!(actual.communication_structure, CommunicationStructure::Structured);
+        assert_eq!(actual.communication, "101269021157996", "communication should keep its type");
+
+        let actual = Movement::parse_type1(unstructured).unwrap();
+        assert_eq!(actual.communication_structure, CommunicationStructure::Unstructured);
+
+        let invalid = format!("{}2{}", &unstructured[..61], &unstructured[62..]);
+        assert_eq!(Movement::parse_type1(&invalid).is_ok(), false, "Structure '2' should not be ok");
+    }
+}
+
+#[cfg(test)]
+mod test_parse_movement_fields {
+    use super::Account;
+    use super::CommunicationStructure;
+    use super::Movement;
+    use super::RTransaction;
+    use iso20022::{CategoryPurpose, Purpose, ReturnReason};
+
+    // Entry 0006 of the sample file in test-data: records 2.1, 2.2 (with a BIC) and 2.3 (without account)
+    const LINE1: &str = "2100060000OL4754532 BELCTOCOMFG0000000002500000061206001500000RISTOURNE COMMERCIALE FRAIS D'ENTREE CBC FONDS REVERS06120600101 0";
+    const LINE2: &str = "2200060000E SPREAD 1                                                                              CREGBEBB                   1 0";
+    const LINE3: &str = "2300060000000000000000                         CBC BANQUE S.A.                                                               0 1";
+
+    // Record 2.3 of entry 0003 of the sample file, with a foreign IBAN
+    const LINE3_IBAN: &str = "2300030000LU037050522702273100                  Olgerdin Egill Skallagrims                                                   0 1";
+
+    fn parse_movement(line2: &str, line3: &str) -> Movement {
+        let mut movement = Movement::parse_type1(LINE1).unwrap();
+        movement.parse_type2(line2).unwrap();
+        movement.parse_type3(line3).unwrap();
+        movement
+    }
+
+    // The sample file has no SEPA codes, they are written in their zones of its record 2.2
+    fn with_zone(line: &str, start: usize, text: &str) -> String {
+        format!("{}{}{}", &line[..start], text, &line[start + text.len()..])
+    }
+
+    #[test]
+    fn parse_movement_customer_reference() {
+        let actual = parse_movement(&with_zone(LINE2, 63, "E2E-REF-2018-0001"), LINE3);
+        assert_eq!(actual.customer_reference, Some(String::from("E2E-REF-2018-0001")));
+
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(actual.customer_reference, Some(String::new()), "Blank zone");
+    }
+
+    #[test]
+    fn parse_movement_counterparty_bic() {
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(actual.counterparty_bic, Some(String::from("CREGBEBB")));
+    }
+
+    #[test]
+    fn parse_movement_r_transaction() {
+        let actual = parse_movement(&with_zone(LINE2, 112, "2"), LINE3);
+        assert_eq!(actual.r_transaction, Some(RTransaction::Return));
+
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(actual.r_transaction, None, "Blank zone");
+    }
+
+    #[test]
+    fn parse_movement_r_reason() {
+        let actual = parse_movement(&with_zone(LINE2, 113, "MD06"), LINE3);
+        assert_eq!(actual.r_reason, Some(ReturnReason::RefundRequestByEndCustomer));
+    }
+
+    #[test]
+    fn parse_movement_category_purpose() {
+        let actual = parse_movement(&with_zone(LINE2, 117, "SUPP"), LINE3);
+        assert_eq!(actual.category_purpose, Some(CategoryPurpose::SupplierPayment));
+    }
+
+    #[test]
+    fn parse_movement_purpose() {
+        let actual = parse_movement(&with_zone(LINE2, 121, "GDDS"), LINE3);
+        assert_eq!(actual.purpose, Some(Purpose::PurchaseSaleOfGoods));
+    }
+
+    #[test]
+    fn parse_movement_counterparty_account_and_currency() {
+        let actual = parse_movement(LINE2, LINE3_IBAN);
+        assert_eq!(
+            actual.counterparty_account,
+            Some(Account::IBANForeignAccountNumber {
+                number: String::from("LU037050522702273100"),
+                currency: String::from(""),
+            })
+        );
+
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(actual.counterparty_account, None, "An account of zeros is no account");
+    }
+
+    #[test]
+    fn parse_movement_counterparty_name() {
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(actual.counterparty_name, Some(String::from("CBC BANQUE S.A.")));
+    }
+
+    #[test]
+    fn parse_movement_communication() {
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(
+            actual.communication,
+            "RISTOURNE COMMERCIALE FRAIS D'ENTREE CBC FONDS REVERS\nE SPREAD 1\n"
+        );
+    }
+
+    #[test]
+    fn parse_movement_type3_communication_up_to_column_125() {
+        // The 2.3 communication zone runs from column 83 to 125
+        let actual = parse_movement(LINE2, &with_zone(LINE3, 82, "INVOICE 2018/0042 DELIVERY NOTE 77 LAST8CHR"));
+        assert_eq!(
+            actual.communication.ends_with("\nINVOICE 2018/0042 DELIVERY NOTE 77 LAST8CHR"),
+            true,
+            "Columns 118-125 should be kept"
+        );
+    }
+
+    #[test]
+    fn parse_movement_structured_reference() {
+        let line1 = "2100530000IKKUZ0AAAAOVSBBNONTVA0000000000817560061206001500001101269021157996                                      06120600101 0";
+        let actual = Movement::parse_type1(line1).unwrap();
+        assert_eq!(actual.communication_structure, CommunicationStructure::Structured);
+        assert_eq!(actual.structured_reference(), Some(String::from("269021157996")));
+
+        let actual = parse_movement(LINE2, LINE3);
+        assert_eq!(actual.communication_structure, CommunicationStructure::Unstructured);
+        assert_eq!(actual.structured_reference(), None);
+    }
+
+    #[test]
+    fn parse_movement_continuation_codes() {
+        let mut movement = Movement::parse_type1(LINE1).unwrap();
+        assert_eq!(movement.next_code, true, "2.1 next_code should be true");
+        assert_eq!(movement.link_code, false, "2.1 link_code should be false");
+
+        movement.parse_type2(LINE2).unwrap();
+        assert_eq!(movement.next_code, true, "2.2 next_code should be true");
+        assert_eq!(movement.link_code, false, "2.2 link_code should be false");
+
+        movement.parse_type3(LINE3).unwrap();
+        assert_eq!(movement.next_code, false, "2.3 next_code should be false");
+        assert_eq!(movement.link_code, true, "2.3 link_code should be true");
+    }
+
+    #[test]
+    fn parse_movement_blank_continuation_codes() {
+        let mut movement = Movement::parse_type1(&with_zone(LINE1, 125, " ")).unwrap();
+        assert_eq!(movement.next_code, false, "A blank next_code should be false");
+
+        let result = movement.parse_type3(&LINE3[..125]);
+        assert_eq!(result.is_ok(), true, "A record without codes should parse");
+        assert_eq!(movement.next_code, false, "A missing next_code should be false");
+        assert_eq!(movement.link_code, false, "A missing link_code should be false");
+    }
 }
 
 #[cfg(test)]
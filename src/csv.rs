@@ -0,0 +1,382 @@
+use std::str::FromStr;
+
+use std::fmt::Write;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDate;
+
+use coda::{Coda, CommunicationStructure};
+use movements::movement_rows;
+use utils::{format_amount, signed_amount, Sign};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CsvTable {
+    Movements,
+    Statements,
+    Information,
+}
+
+impl FromStr for CsvTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<CsvTable, String> {
+        match s {
+            "movements" => Ok(CsvTable::Movements),
+            "statements" => Ok(CsvTable::Statements),
+            "information" => Ok(CsvTable::Information),
+            _ => Err(format!("Invalid csv table [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CsvQuoting {
+    // Only quote fields containing the delimiter, a quote or a line break
+    Minimal,
+    All,
+}
+
+impl FromStr for CsvQuoting {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<CsvQuoting, String> {
+        match s {
+            "minimal" => Ok(CsvQuoting::Minimal),
+            "all" => Ok(CsvQuoting::All),
+            _ => Err(format!("Invalid csv quoting [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub decimal_separator: char,
+    pub date_format: String,
+    pub header: bool,
+    pub quoting: CsvQuoting,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: ',',
+            decimal_separator: '.',
+            date_format: String::from("%Y-%m-%d"),
+            header: true,
+            quoting: CsvQuoting::Minimal,
+        }
+    }
+}
+
+impl CsvOptions {
+    // Checked while parsing the options, writing a date with an invalid format would panic
+    pub fn validate(&self) -> ::std::result::Result<(), String> {
+        if ['"', '\r', '\n'].contains(&self.delimiter) {
+            return Err(format!("Invalid csv delimiter [{}]", self.delimiter.escape_default()));
+        }
+        let invalid_format = || format!("Invalid csv date format [{}]", self.date_format);
+        if StrftimeItems::new(&self.date_format).any(|item| item == Item::Error) {
+            return Err(invalid_format());
+        }
+        // Time fields are valid strftime but a date can not write them
+        let date = NaiveDate::from_ymd(2006, 12, 6);
+        write!(String::new(), "{}", date.format(&self.date_format)).map_err(|_| invalid_format())
+    }
+}
+
+struct CsvWriter<'a> {
+    options: &'a CsvOptions,
+    output: String,
+}
+
+impl<'a> CsvWriter<'a> {
+    fn new(options: &'a CsvOptions) -> CsvWriter<'a> {
+        CsvWriter {
+            options,
+            output: String::new(),
+        }
+    }
+
+    fn escape(&self, field: &str) -> String {
+        let needs_quotes = self.options.quoting == CsvQuoting::All || field.contains(self.options.delimiter)
+            || field.contains('"') || field.contains('\n') || field.contains('\r');
+        if needs_quotes {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            String::from(field)
+        }
+    }
+
+    fn write_record(&mut self, fields: &[String]) {
+        let delimiter = self.options.delimiter.to_string();
+        let record = fields
+            .iter()
+            .map(|f| self.escape(f))
+            .collect::<Vec<String>>()
+            .join(&delimiter);
+        self.output.push_str(&record);
+        self.output.push_str("\r\n");
+    }
+
+    fn write_header(&mut self, names: &[&str]) {
+        if self.options.header {
+            let names = names.iter().map(|n| String::from(*n)).collect::<Vec<String>>();
+            self.write_record(&names);
+        }
+    }
+
+    fn date(&self, date: &NaiveDate) -> String {
+        format!("{}", date.format(&self.options.date_format))
+    }
+
+    fn amount(&self, amount: i64) -> String {
+        format_amount(amount, self.options.decimal_separator)
+    }
+}
+
+fn opt(value: &Option<String>) -> String {
+    value.clone().unwrap_or_default()
+}
+
+fn sign_str(sign: &Sign) -> String {
+    String::from(match *sign {
+        Sign::Credit => "C",
+        Sign::Debit => "D",
+    })
+}
+
+fn write_movements(writer: &mut CsvWriter, coda_list: &[Coda]) {
+    writer.write_header(&[
+        "file_reference",
+        "creation_date",
+        "account",
+        "currency",
+        "statement_number",
+        "sequence",
+        "detail_sequence",
+        "entry",
+        "bank_reference",
+        "value_date",
+        "entry_date",
+        "sign",
+        "amount",
+        "transaction_code",
+        "transaction_family",
+        "communication",
+        "customer_reference",
+        "counterparty_name",
+        "counterparty_account",
+        "counterparty_bic",
+    ]);
+    for row in movement_rows(coda_list) {
+        let record = vec![
+            row.file_reference.clone(),
+            writer.date(&row.creation_date),
+            row.account.clone(),
+            row.currency.clone(),
+            row.statement_number.clone(),
+            row.sequence.clone(),
+            row.detail_sequence.clone(),
+            row.entry.to_string(),
+            row.bank_reference.clone(),
+            writer.date(&row.value_date),
+            writer.date(&row.entry_date),
+            sign_str(&row.sign),
+            writer.amount(row.signed_amount),
+            row.transaction_code.clone(),
+            opt(&row.transaction_family_description),
+            row.communication.clone(),
+            opt(&row.customer_reference),
+            opt(&row.counterparty_name),
+            opt(&row.counterparty_account),
+            opt(&row.counterparty_bic),
+        ];
+        writer.write_record(&record);
+    }
+}
+
+fn write_statements(writer: &mut CsvWriter, coda_list: &[Coda]) {
+    writer.write_header(&[
+        "file_reference",
+        "creation_date",
+        "account",
+        "currency",
+        "statement_number",
+        "old_balance_date",
+        "old_balance",
+        "new_balance_date",
+        "new_balance",
+        "movements",
+        "total_debit",
+        "total_credit",
+    ]);
+    for coda in coda_list {
+        let old_balance = &coda.old_balance;
+        let new_balance = &coda.new_balance;
+        let record = vec![
+            String::from(coda.header.file_reference.trim()),
+            writer.date(&coda.header.creation_date),
+            String::from(old_balance.account.number()),
+            String::from(old_balance.account.currency()),
+            old_balance.old_sequence.clone(),
+            writer.date(&old_balance.old_balance_date),
+            writer.amount(signed_amount(&old_balance.old_balance_sign, old_balance.old_balance)),
+            writer.date(&new_balance.new_balance_date),
+            writer.amount(signed_amount(&new_balance.new_balance_sign, new_balance.new_balance)),
+            coda.entries().len().to_string(),
+            writer.amount(coda.trailer.total_debit as i64),
+            writer.amount(coda.trailer.total_credit as i64),
+        ];
+        writer.write_record(&record);
+    }
+}
+
+fn write_information(writer: &mut CsvWriter, coda_list: &[Coda]) {
+    writer.write_header(&[
+        "file_reference",
+        "account",
+        "sequence",
+        "detail_sequence",
+        "bank_reference",
+        "transaction_code",
+        "structured",
+        "communication",
+    ]);
+    for coda in coda_list {
+        for information in &coda.information {
+            let structured = information.communication_structure == CommunicationStructure::Structured;
+            let record = vec![
+                String::from(coda.header.file_reference.trim()),
+                String::from(coda.old_balance.account.number()),
+                information.sequence.clone(),
+                information.detail_sequence.clone(),
+                information.bank_reference.clone(),
+                information.transaction_code.clone(),
+                String::from(if structured { "1" } else { "0" }),
+                information.communication.clone(),
+            ];
+            writer.write_record(&record);
+        }
+    }
+}
+
+pub fn to_csv(coda_list: &[Coda], table: CsvTable, options: &CsvOptions) -> String {
+    let mut writer = CsvWriter::new(options);
+    match table {
+        CsvTable::Movements => write_movements(&mut writer, coda_list),
+        CsvTable::Statements => write_statements(&mut writer, coda_list),
+        CsvTable::Information => write_information(&mut writer, coda_list),
+    }
+    writer.output
+}
+
+// Records already formatted by the caller, ie query results
+pub fn records_to_csv(names: &[&str], records: &[Vec<String>], options: &CsvOptions) -> String {
+    let mut writer = CsvWriter::new(options);
+    writer.write_header(names);
+    for record in records {
+        writer.write_record(record);
+    }
+    writer.output
+}
+
+#[cfg(test)]
+mod test_csv {
+    use super::*;
+    use fixtures::parse_coda_list;
+
+    #[test]
+    fn validate_options() {
+        let options = |delimiter: char, date_format: &str| CsvOptions {
+            delimiter,
+            date_format: String::from(date_format),
+            ..CsvOptions::default()
+        };
+        assert_eq!(CsvOptions::default().validate(), Ok(()));
+        assert_eq!(options(';', "%d/%m/%Y").validate(), Ok(()));
+        assert_eq!(options('\t', "%e %B %Y").validate(), Ok(()));
+        assert_eq!(options(',', "%Q").validate().is_err(), true, "Unknown specifier");
+        assert_eq!(options(',', "%Y-%").validate().is_err(), true, "Truncated specifier");
+        assert_eq!(options(',', "%Y %H:%M").validate().is_err(), true, "Time specifier");
+        assert_eq!(options('"', "%Y").validate().is_err(), true, "Quote delimiter");
+        assert_eq!(options('\n', "%Y").validate().is_err(), true, "Line break delimiter");
+        assert_eq!(options('\r', "%Y").validate().is_err(), true, "Carriage return delimiter");
+    }
+
+    #[test]
+    fn to_csv_movements_valid() {
+        let coda_list = parse_coda_list();
+
+        let actual = to_csv(&coda_list, CsvTable::Movements, &CsvOptions::default());
+
+        let mut lines = actual.split("\r\n");
+        assert_eq!(
+            lines.next().unwrap().starts_with("file_reference,creation_date,account"),
+            true,
+            "First line should be the header"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "00099449,2006-12-06,435000000080,EUR,001,0001,0000,true,EPIB00048 AWIUBTKAPUO,2006-12-06,2006-12-06,D,\
+             -2578.250,00799000,Domestic commercial paper,\"BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI\nON \
+             495953\",,,,"
+        );
+    }
+
+    #[test]
+    fn to_csv_belgian_excel() {
+        let coda_list = parse_coda_list();
+        let options = CsvOptions {
+            delimiter: ';',
+            decimal_separator: ',',
+            date_format: String::from("%d/%m/%Y"),
+            header: false,
+            quoting: CsvQuoting::Minimal,
+        };
+
+        let actual = to_csv(&coda_list, CsvTable::Statements, &options);
+
+        assert_eq!(
+            actual,
+            "00099449;06/12/2006;435000000080;EUR;001;06/12/2006;0,000;07/12/2006;9405296,990;59;3085871,600;\
+             12491168,590\r\n"
+        );
+    }
+
+    #[test]
+    fn to_csv_information_quote_all() {
+        let coda_list = parse_coda_list();
+        let options = CsvOptions {
+            quoting: CsvQuoting::All,
+            ..CsvOptions::default()
+        };
+
+        let actual = to_csv(&coda_list, CsvTable::Information, &options);
+
+        let line = actual.split("\r\n").nth(1).unwrap();
+        assert_eq!(
+            line.starts_with("\"00099449\",\"435000000080\",\"0003\",\"0001\""),
+            true,
+            "All fields should be quoted"
+        );
+    }
+
+    #[test]
+    fn escape_quotes() {
+        let options = CsvOptions::default();
+        let writer = CsvWriter::new(&options);
+
+        assert_eq!(writer.escape("SAY \"HELLO\""), "\"SAY \"\"HELLO\"\"\"");
+        assert_eq!(writer.escape("A,B"), "\"A,B\"");
+        assert_eq!(writer.escape("AB"), "AB");
+    }
+
+    #[test]
+    fn csv_table_from_str() {
+        assert_eq!("movements".parse::<CsvTable>(), Ok(CsvTable::Movements));
+        assert_eq!("statements".parse::<CsvTable>(), Ok(CsvTable::Statements));
+        assert_eq!("information".parse::<CsvTable>(), Ok(CsvTable::Information));
+        assert_eq!("balances".parse::<CsvTable>().is_err(), true, "balances should not be ok");
+    }
+}
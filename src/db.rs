@@ -0,0 +1,297 @@
+use rusqlite::{Connection, Transaction};
+
+use coda::{Coda, CommunicationStructure};
+use utils::signed_amount;
+
+use errors::*;
+
+// Amounts are stored as signed integers in thousandths, dates as ISO 8601 text
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    filename TEXT NOT NULL,
+    file_reference TEXT NOT NULL,
+    creation_date TEXT NOT NULL,
+    bank_id TEXT NOT NULL,
+    bic TEXT NOT NULL,
+    name_addressee TEXT NOT NULL,
+    duplicate INTEGER NOT NULL,
+    version INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS statements (
+    id INTEGER PRIMARY KEY,
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    account TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    sequence TEXT NOT NULL,
+    account_holder_name TEXT NOT NULL,
+    account_description TEXT NOT NULL,
+    old_balance INTEGER NOT NULL,
+    old_balance_date TEXT NOT NULL,
+    new_balance INTEGER NOT NULL,
+    new_balance_date TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS movements (
+    id INTEGER PRIMARY KEY,
+    statement_id INTEGER NOT NULL REFERENCES statements(id),
+    sequence TEXT NOT NULL,
+    detail_sequence TEXT NOT NULL,
+    bank_reference TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    value_date TEXT NOT NULL,
+    entry_date TEXT NOT NULL,
+    transaction_code TEXT NOT NULL,
+    structured INTEGER NOT NULL,
+    communication TEXT NOT NULL,
+    customer_reference TEXT,
+    counterparty_name TEXT,
+    counterparty_account TEXT,
+    counterparty_bic TEXT,
+    r_reason TEXT,
+    category_purpose TEXT,
+    purpose TEXT
+);
+CREATE TABLE IF NOT EXISTS information (
+    id INTEGER PRIMARY KEY,
+    statement_id INTEGER NOT NULL REFERENCES statements(id),
+    sequence TEXT NOT NULL,
+    detail_sequence TEXT NOT NULL,
+    bank_reference TEXT NOT NULL,
+    transaction_code TEXT NOT NULL,
+    structured INTEGER NOT NULL,
+    communication TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS free_communications (
+    id INTEGER PRIMARY KEY,
+    statement_id INTEGER NOT NULL REFERENCES statements(id),
+    sequence TEXT NOT NULL,
+    detail_sequence TEXT NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS movements_statement ON movements(statement_id);
+CREATE INDEX IF NOT EXISTS statements_account ON statements(account, sequence);
+";
+
+#[derive(PartialEq, Debug, Default)]
+pub struct ImportSummary {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+fn is_structured(structure: &CommunicationStructure) -> bool {
+    *structure == CommunicationStructure::Structured
+}
+
+// A file is already imported when a statement with the same file reference, account, sequence and duplicate flag
+// exists
+fn is_imported(tx: &Transaction, coda: &Coda) -> Result<bool> {
+    let count: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM files f JOIN statements s ON s.file_id = f.id \
+         WHERE f.file_reference = ?1 AND s.account = ?2 AND s.sequence = ?3 AND f.duplicate = ?4",
+        params![
+            coda.header.file_reference.trim(),
+            coda.old_balance.account.number(),
+            coda.old_balance.old_sequence,
+            coda.header.duplicate,
+        ],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn insert_coda(tx: &Transaction, filename: &str, coda: &Coda) -> Result<()> {
+    let header = &coda.header;
+    tx.execute(
+        "INSERT INTO files (filename, file_reference, creation_date, bank_id, bic, name_addressee, duplicate, version) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            filename,
+            header.file_reference.trim(),
+            header.creation_date.to_string(),
+            header.bank_id,
+            header.bic,
+            header.name_addressee,
+            header.duplicate,
+            header.version,
+        ],
+    )?;
+    let file_id = tx.last_insert_rowid();
+
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+    tx.execute(
+        "INSERT INTO statements (file_id, account, currency, sequence, account_holder_name, account_description, \
+         old_balance, old_balance_date, new_balance, new_balance_date) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            file_id,
+            old_balance.account.number(),
+            old_balance.account.currency(),
+            old_balance.old_sequence,
+            old_balance.account_holder_name,
+            old_balance.account_description,
+            signed_amount(&old_balance.old_balance_sign, old_balance.old_balance),
+            old_balance.old_balance_date.to_string(),
+            signed_amount(&new_balance.new_balance_sign, new_balance.new_balance),
+            new_balance.new_balance_date.to_string(),
+        ],
+    )?;
+    let statement_id = tx.last_insert_rowid();
+
+    {
+        let mut statement = tx.prepare(
+            "INSERT INTO movements (statement_id, sequence, detail_sequence, bank_reference, amount, value_date, \
+             entry_date, transaction_code, structured, communication, customer_reference, counterparty_name, \
+             counterparty_account, counterparty_bic, r_reason, category_purpose, purpose) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        )?;
+        for movement in &coda.movements {
+            statement.execute(params![
+                statement_id,
+                movement.sequence,
+                movement.detail_sequence,
+                movement.bank_reference.trim(),
+                movement.signed_amount(),
+                movement.value_date.to_string(),
+                movement.entry_date.to_string(),
+                movement.transaction_code,
+                is_structured(&movement.communication_structure),
+                movement.communication,
+                movement.customer_reference,
+                movement.counterparty_name,
+                movement.counterparty_account.as_ref().map(|a| a.number()),
+                movement.counterparty_bic,
+                movement.r_reason.as_ref().map(|r| r.code()),
+                movement.category_purpose.as_ref().map(|p| p.code()),
+                movement.purpose.as_ref().map(|p| p.code()),
+            ])?;
+        }
+    }
+
+    {
+        let mut statement = tx.prepare(
+            "INSERT INTO information (statement_id, sequence, detail_sequence, bank_reference, transaction_code, \
+             structured, communication) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for information in &coda.information {
+            statement.execute(params![
+                statement_id,
+                information.sequence,
+                information.detail_sequence,
+                information.bank_reference.trim(),
+                information.transaction_code,
+                is_structured(&information.communication_structure),
+                information.communication,
+            ])?;
+        }
+    }
+
+    {
+        let mut statement = tx.prepare(
+            "INSERT INTO free_communications (statement_id, sequence, detail_sequence, text) \
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for free_communication in &coda.free_communications {
+            statement.execute(params![
+                statement_id,
+                free_communication.sequence,
+                free_communication.detail_sequence,
+                free_communication.text,
+            ])?;
+        }
+    }
+
+    Ok(())
+}
+
+// All the files are imported in one transaction, nothing is written when one of them fails
+pub fn import(connection: &mut Connection, files: &[(&str, &Coda)]) -> Result<ImportSummary> {
+    connection
+        .execute_batch(SCHEMA)
+        .chain_err(|| "Could not create database schema")?;
+    let tx = connection.transaction()?;
+    let mut summary = ImportSummary::default();
+    for &(filename, coda) in files {
+        if is_imported(&tx, coda)? {
+            summary.skipped.push(String::from(filename));
+        } else {
+            insert_coda(&tx, filename, coda).chain_err(|| format!("Could not import {}", filename))?;
+            summary.imported.push(String::from(filename));
+        }
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+pub fn import_file(db_filename: &str, files: &[(&str, &Coda)]) -> Result<ImportSummary> {
+    let mut connection =
+        Connection::open(db_filename).chain_err(|| format!("Unable to open database {}", db_filename))?;
+    import(&mut connection, files)
+}
+
+#[cfg(test)]
+mod test_db {
+    use super::*;
+    use fixtures::parse_coda;
+
+    fn count(connection: &Connection, table: &str) -> i64 {
+        connection
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), params![], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn import_valid() {
+        let coda = parse_coda();
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        let actual = import(&mut connection, &[("CODA.txt", &coda)]);
+
+        assert_eq!(actual.is_ok(), true, "Import should be ok");
+        assert_eq!(actual.unwrap().imported, vec!["CODA.txt"]);
+        assert_eq!(count(&connection, "files"), 1);
+        assert_eq!(count(&connection, "statements"), 1);
+        assert_eq!(count(&connection, "movements"), coda.movements.len() as i64);
+        assert_eq!(count(&connection, "information"), coda.information.len() as i64);
+        assert_eq!(count(&connection, "free_communications"), coda.free_communications.len() as i64);
+        let new_balance: i64 = connection
+            .query_row("SELECT new_balance FROM statements", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(new_balance, 9405296990);
+        let amount: i64 = connection
+            .query_row(
+                "SELECT amount FROM movements WHERE sequence = '0001' AND detail_sequence = '0000'",
+                params![],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(amount, -2578250);
+    }
+
+    #[test]
+    fn import_incremental() {
+        let coda = parse_coda();
+        let mut connection = Connection::open_in_memory().unwrap();
+        import(&mut connection, &[("CODA.txt", &coda)]).unwrap();
+
+        let actual = import(&mut connection, &[("CODA-copy.txt", &coda)]).unwrap();
+
+        assert_eq!(actual.imported.len(), 0, "Nothing should be imported twice");
+        assert_eq!(actual.skipped, vec!["CODA-copy.txt"]);
+        assert_eq!(count(&connection, "files"), 1);
+        assert_eq!(count(&connection, "movements"), coda.movements.len() as i64);
+    }
+
+    #[test]
+    fn import_duplicate_flag() {
+        let coda = parse_coda();
+        let mut duplicate = parse_coda();
+        duplicate.header.duplicate = !coda.header.duplicate;
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        let actual = import(&mut connection, &[("CODA.txt", &coda), ("CODA-dup.txt", &duplicate)]).unwrap();
+
+        assert_eq!(actual.imported.len(), 2, "A duplicate flagged file is another file");
+        assert_eq!(count(&connection, "statements"), 2);
+    }
+}
@@ -1,4 +1,5 @@
 use chrono;
+use rusqlite;
 use serde_json;
 
 // Create the Error, ErrorKind, ResultExt, and Result types
@@ -6,5 +7,6 @@ error_chain!{
     foreign_links {
         ParseError(chrono::format::ParseError);
         SerdeJsonError(serde_json::error::Error);
+        SqliteError(rusqlite::Error);
     }
 }
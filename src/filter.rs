@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDate;
+
+use coda::{Coda, Movement};
+use utils::Sign;
+
+use errors::*;
+
+// Criteria on the booked entries of a statement, an entry is kept with its details when all the criteria match
+#[derive(PartialEq, Debug, Default)]
+pub struct MovementFilter {
+    // Value date range, both included
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    // Unsigned amounts in thousandths, both included
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    pub sign: Option<Sign>,
+    // Case insensitive part of the counterparty name or account number
+    pub counterparty: Option<String>,
+    // Transaction code family (positions 2-3 of the transaction code)
+    pub family: Option<String>,
+    // Structured creditor reference (OGM), 12 digits with or without +++/ formatting
+    pub structured_reference: Option<String>,
+    // Case insensitive part of the communication of the entry or one of its details
+    pub grep: Option<String>,
+}
+
+// Amount with up to 3 decimals (ie 1000, 1000.5 or 1000,50) as thousandths
+pub fn parse_amount(s: &str) -> Result<u64> {
+    let s = s.trim().replace(',', ".");
+    let mut parts = s.splitn(2, '.');
+    let units = parts.next().unwrap_or("");
+    let decimals = parts.next().unwrap_or("");
+    if units.is_empty() || decimals.len() > 3 || !units.chars().chain(decimals.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid amount [{}]", s).into());
+    }
+    let units = units
+        .parse::<u64>()
+        .chain_err(|| format!("Invalid amount [{}]", s))?;
+    let decimals = format!("{:0<3}", decimals).parse::<u64>().unwrap();
+    units
+        .checked_mul(1000)
+        .and_then(|amount| amount.checked_add(decimals))
+        .ok_or_else(|| format!("Invalid amount [{}]", s).into())
+}
+
+fn parse_filter_date(s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").chain_err(|| format!("Invalid date [{}], use yyyy-mm-dd", s))
+}
+
+fn digits(s: &str) -> String {
+    s.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn contains_ignore_case(text: &str, part: &str) -> bool {
+    text.to_lowercase().contains(&part.to_lowercase())
+}
+
+const FILTER_KEYS: [&str; 9] = [
+    "from",
+    "to",
+    "min-amount",
+    "max-amount",
+    "sign",
+    "counterparty",
+    "family",
+    "ogm",
+    "grep",
+];
+
+// A comma only starts a new criterion when a known key= follows it, values can hold commas (ie counterparty=ACME, INC)
+fn starts_criterion(part: &str) -> bool {
+    part.split_once('=')
+        .is_some_and(|(key, _)| FILTER_KEYS.contains(&key.trim()))
+}
+
+impl MovementFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == MovementFilter::default()
+    }
+
+    // Adds a criterion written as key=value, several can be separated by commas (ie sign=debit,family=01).
+    // A value runs up to the next key=, so it can hold commas (ie grep=a,b or min-amount=1000,50).
+    pub fn add_expression(&mut self, expression: &str) -> Result<()> {
+        let mut criteria: Vec<String> = Vec::new();
+        for part in expression.split(',') {
+            match criteria.last_mut() {
+                Some(criterion) if !starts_criterion(part) => {
+                    criterion.push(',');
+                    criterion.push_str(part);
+                }
+                _ => criteria.push(String::from(part)),
+            }
+        }
+        for criterion in criteria.iter().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+            let mut parts = criterion.splitn(2, '=');
+            let key = parts.next().unwrap().trim();
+            let value = parts
+                .next()
+                .ok_or_else(|| format!("Invalid filter [{}], use key=value", criterion))?;
+            self.add_criterion(key, value.trim())?;
+        }
+        Ok(())
+    }
+
+    pub fn add_criterion(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "from" => self.from = Some(parse_filter_date(value)?),
+            "to" => self.to = Some(parse_filter_date(value)?),
+            "min-amount" => self.min_amount = Some(parse_amount(value)?),
+            "max-amount" => self.max_amount = Some(parse_amount(value)?),
+            "sign" => {
+                self.sign = Some(match value {
+                    "debit" => Sign::Debit,
+                    "credit" => Sign::Credit,
+                    _ => return Err(format!("Invalid sign [{}], use debit or credit", value).into()),
+                })
+            }
+            "counterparty" => self.counterparty = Some(String::from(value)),
+            "family" => self.family = Some(String::from(value)),
+            "ogm" => {
+                let reference = digits(value);
+                if reference.len() != 12 {
+                    return Err(format!("Invalid structured reference [{}], 12 digits expected", value).into());
+                }
+                self.structured_reference = Some(reference)
+            }
+            "grep" => self.grep = Some(String::from(value)),
+            _ => return Err(format!("Unknown filter [{}]", key).into()),
+        }
+        Ok(())
+    }
+
+    fn matches_counterparty(&self, entry: &[&Movement]) -> bool {
+        let counterparty = match self.counterparty {
+            Some(ref counterparty) => counterparty,
+            None => return true,
+        };
+        entry.iter().any(|m| {
+            m.counterparty_name
+                .as_ref()
+                .map_or(false, |name| contains_ignore_case(name, counterparty))
+                || m.counterparty_account
+                    .as_ref()
+                    .map_or(false, |account| contains_ignore_case(account.number(), counterparty))
+        })
+    }
+
+    // Dates, amount, sign and transaction code are the ones of the booked entry, texts are searched in the details
+    pub fn matches(&self, entry: &[&Movement]) -> bool {
+        let movement = entry[0];
+        let amount = movement.unsigned_amount();
+        self.from.map_or(true, |from| movement.value_date >= from)
+            && self.to.map_or(true, |to| movement.value_date <= to)
+            && self.min_amount.map_or(true, |min| amount >= min)
+            && self.max_amount.map_or(true, |max| amount <= max)
+            && self.sign.as_ref().map_or(true, |sign| movement.sign() == *sign)
+            && self.family
+                .as_ref()
+                .map_or(true, |family| movement.transaction_code.get(1..3) == Some(family.as_str()))
+            && self.structured_reference.as_ref().map_or(true, |reference| {
+                entry.iter().any(|m| m.structured_reference().as_ref() == Some(reference))
+            })
+            && self.grep.as_ref().map_or(true, |grep| {
+                entry.iter().any(|m| contains_ignore_case(&m.communication, grep))
+            })
+            && self.matches_counterparty(entry)
+    }
+
+    // Keeps the matching entries and their details
+    pub fn apply(&self, coda: &mut Coda) {
+        if self.is_empty() {
+            return;
+        }
+        let kept = coda.entries()
+            .iter()
+            .filter(|entry| self.matches(entry))
+            .map(|entry| entry[0].sequence.clone())
+            .collect::<HashSet<String>>();
+        coda.movements.retain(|m| kept.contains(&m.sequence));
+    }
+}
+
+#[cfg(test)]
+mod test_filter {
+    use super::*;
+    use fixtures::parse_coda;
+
+    fn filter(expression: &str) -> MovementFilter {
+        let mut filter = MovementFilter::default();
+        filter.add_expression(expression).unwrap();
+        filter
+    }
+
+    fn sequences(coda: &Coda) -> Vec<String> {
+        coda.entries().iter().map(|e| e[0].sequence.clone()).collect()
+    }
+
+    #[test]
+    fn parse_amount_valid() {
+        assert_eq!(parse_amount("1000").unwrap(), 1000000);
+        assert_eq!(parse_amount("1000.5").unwrap(), 1000500);
+        assert_eq!(parse_amount("11,21").unwrap(), 11210);
+        assert_eq!(parse_amount("1.2345").is_err(), true, "Only 3 decimals");
+        assert_eq!(parse_amount("-5").is_err(), true, "Amounts are unsigned");
+        assert_eq!(parse_amount("abc").is_err(), true, "Not an amount");
+        assert_eq!(
+            parse_amount("99999999999999999").is_err(),
+            true,
+            "An amount out of range should be an error"
+        );
+    }
+
+    #[test]
+    fn add_expression_valid() {
+        let actual = filter("sign=debit, family=01,ogm=+++269/0211/57996+++,min-amount=10");
+
+        assert_eq!(actual.sign, Some(Sign::Debit));
+        assert_eq!(actual.family, Some(String::from("01")));
+        assert_eq!(actual.structured_reference, Some(String::from("269021157996")));
+        assert_eq!(actual.min_amount, Some(10000));
+    }
+
+    #[test]
+    fn add_expression_commas_in_values() {
+        let actual = filter("counterparty=ACME, INC,grep=a,b, min-amount=1000,50");
+
+        assert_eq!(actual.counterparty, Some(String::from("ACME, INC")));
+        assert_eq!(actual.grep, Some(String::from("a,b")));
+        assert_eq!(actual.min_amount, Some(1000500), "Decimal comma");
+        assert_eq!(filter("grep=x=1,y").grep, Some(String::from("x=1,y")), "Only known keys start a criterion");
+    }
+
+    #[test]
+    fn add_expression_invalid() {
+        let mut actual = MovementFilter::default();
+
+        assert_eq!(actual.add_expression("colour=red").is_err(), true, "Unknown key");
+        assert_eq!(actual.add_expression("sign").is_err(), true, "Missing value");
+        assert_eq!(actual.add_expression("sign=both").is_err(), true, "Invalid sign");
+        assert_eq!(actual.add_expression("from=06/12/2006").is_err(), true, "Invalid date");
+    }
+
+    #[test]
+    fn apply_empty() {
+        let mut coda = parse_coda();
+        let count = coda.movements.len();
+
+        MovementFilter::default().apply(&mut coda);
+
+        assert_eq!(coda.movements.len(), count, "Nothing should be filtered");
+    }
+
+    #[test]
+    fn apply_sign_and_amount() {
+        let mut coda = parse_coda();
+
+        filter("sign=debit,min-amount=1000").apply(&mut coda);
+
+        let entries = coda.entries();
+        assert_eq!(entries.is_empty(), false, "Large debits should be kept");
+        assert_eq!(
+            entries
+                .iter()
+                .all(|e| e[0].sign() == Sign::Debit && e[0].unsigned_amount() >= 1000000),
+            true,
+            "Only large debits should be kept"
+        );
+    }
+
+    #[test]
+    fn apply_keeps_details() {
+        let mut coda = parse_coda();
+        let expected = coda.movements.iter().filter(|m| m.sequence == "0007").count();
+
+        filter("counterparty=ECONOLER").apply(&mut coda);
+
+        assert_eq!(sequences(&coda), vec!["0007"]);
+        assert_eq!(coda.movements.len(), expected, "Details should be kept with their entry");
+    }
+
+    #[test]
+    fn apply_structured_reference() {
+        let mut coda = parse_coda();
+
+        filter("ogm=269021157996").apply(&mut coda);
+
+        assert_eq!(sequences(&coda), vec!["0053"]);
+    }
+
+    #[test]
+    fn apply_dates_and_grep() {
+        let mut coda = parse_coda();
+
+        filter("from=2006-12-06,to=2006-12-06,grep=bordereau").apply(&mut coda);
+
+        assert_eq!(sequences(&coda), vec!["0001"]);
+    }
+}
@@ -0,0 +1,29 @@
+extern crate roxmltree;
+
+pub use self::roxmltree::{Document, Node};
+
+use coda::Coda;
+
+// The sample statement of test-data, read by the test modules of the exports
+pub fn parse_coda() -> Coda {
+    Coda::parse("test-data/CODA.txt", "latin1").unwrap()
+}
+
+pub fn parse_coda_list() -> Vec<Coda> {
+    vec![parse_coda()]
+}
+
+// Xml helpers of the camt.053 and OFX tests, elements are found by their local name
+pub fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Node<'a, 'input> {
+    node.children()
+        .find(|n| n.tag_name().name() == name)
+        .unwrap_or_else(|| panic!("{} should have a {} child", node.tag_name().name(), name))
+}
+
+pub fn path<'a, 'input>(node: Node<'a, 'input>, names: &[&str]) -> Node<'a, 'input> {
+    names.iter().fold(node, |node, name| child(node, name))
+}
+
+pub fn children<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Vec<Node<'a, 'input>> {
+    node.children().filter(|n| n.tag_name().name() == name).collect()
+}
@@ -0,0 +1,318 @@
+use coda::{Coda, Movement};
+use utils::{format_amount, signed_amount, Sign};
+use validation::{validate, Severity};
+use xml::XmlWriter;
+
+const STYLE: &str = "
+body { font-family: Helvetica, Arial, sans-serif; font-size: 10pt; color: #222; margin: 2em; }
+h1 { font-size: 16pt; border-bottom: 2px solid #444; padding-bottom: 0.2em; }
+h2 { font-size: 12pt; margin-top: 1.5em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.2em 0.4em; text-align: left; vertical-align: top; }
+th { background: #eee; }
+table.summary { width: auto; }
+table.summary th { background: none; font-weight: normal; color: #666; }
+td.amount, th.amount { text-align: right; white-space: nowrap; }
+td.debit { color: #b00000; }
+td.credit { color: #006000; }
+tr.detail td { color: #777; font-size: 9pt; }
+tr.total td { font-weight: bold; border-top: 2px solid #444; }
+tr.mismatch td, li.error { background: #fdd; }
+li.warning { background: #ffd; }
+p.ok { color: #006000; }
+@media print { section.statement { page-break-after: always; } body { margin: 0; } }
+";
+
+fn escape(text: &str) -> String {
+    XmlWriter::escape(text).replace('\n', "<br>")
+}
+
+fn amount_cell(amount: i64) -> String {
+    let class = if amount < 0 { "debit" } else { "credit" };
+    format!("<td class=\"amount {}\">{}</td>", class, format_amount(amount, '.'))
+}
+
+fn debit_credit_cells(movement: &Movement) -> String {
+    let amount = format_amount(movement.unsigned_amount() as i64, '.');
+    match movement.sign() {
+        Sign::Debit => format!("<td class=\"amount debit\">{}</td><td></td>", amount),
+        Sign::Credit => format!("<td></td><td class=\"amount credit\">{}</td>", amount),
+    }
+}
+
+fn counterparty(movement: &Movement) -> String {
+    let mut parts = Vec::new();
+    if let Some(ref name) = movement.counterparty_name.as_ref().filter(|n| !n.is_empty()) {
+        parts.push(escape(name));
+    }
+    if let Some(ref account) = movement.counterparty_account {
+        parts.push(escape(account.number()));
+    }
+    if let Some(ref bic) = movement.counterparty_bic.as_ref().filter(|b| !b.is_empty()) {
+        parts.push(escape(bic));
+    }
+    parts.join("<br>")
+}
+
+fn communication(movement: &Movement) -> String {
+    match movement.structured_reference() {
+        Some(reference) => format!(
+            "+++{}/{}/{}+++",
+            &reference[0..3],
+            &reference[3..7],
+            &reference[7..12]
+        ),
+        None => escape(&movement.communication),
+    }
+}
+
+fn write_summary(output: &mut Vec<String>, coda: &Coda) {
+    let header = &coda.header;
+    let old_balance = &coda.old_balance;
+    let rows = [
+        ("File reference", header.file_reference.trim().to_string()),
+        ("Creation date", header.creation_date.to_string()),
+        ("Bank", header.bic.clone()),
+        ("Addressee", header.name_addressee.clone()),
+        ("Account holder", old_balance.account_holder_name.clone()),
+        ("Account description", old_balance.account_description.clone()),
+        ("Duplicate", String::from(if header.duplicate { "Yes" } else { "No" })),
+    ];
+    output.push(String::from("<table class=\"summary\">"));
+    for &(label, ref value) in rows.iter() {
+        output.push(format!("<tr><th>{}</th><td>{}</td></tr>", label, escape(value)));
+    }
+    output.push(String::from("</table>"));
+}
+
+fn write_balances(output: &mut Vec<String>, coda: &Coda) {
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+    let (debit, credit) = coda.entry_totals();
+    output.push(String::from("<h2>Balances</h2>"));
+    output.push(String::from("<table class=\"summary\">"));
+    output.push(format!(
+        "<tr><th>Opening balance {}</th>{}</tr>",
+        old_balance.old_balance_date,
+        amount_cell(signed_amount(&old_balance.old_balance_sign, old_balance.old_balance))
+    ));
+    output.push(format!("<tr><th>Total debit</th>{}</tr>", amount_cell(-(debit as i64))));
+    output.push(format!("<tr><th>Total credit</th>{}</tr>", amount_cell(credit as i64)));
+    output.push(format!(
+        "<tr><th>Closing balance {}</th>{}</tr>",
+        new_balance.new_balance_date,
+        amount_cell(signed_amount(&new_balance.new_balance_sign, new_balance.new_balance))
+    ));
+    output.push(String::from("</table>"));
+}
+
+fn write_findings(output: &mut Vec<String>, coda: &Coda) {
+    let report = validate(coda);
+    output.push(String::from("<h2>Validation</h2>"));
+    if report.findings.is_empty() {
+        output.push(String::from("<p class=\"ok\">No findings</p>"));
+        return;
+    }
+    output.push(String::from("<ul class=\"findings\">"));
+    for finding in &report.findings {
+        let class = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        output.push(format!(
+            "<li class=\"{}\">{:?}: {}: {}</li>",
+            class,
+            finding.severity,
+            escape(&finding.location),
+            escape(&finding.message)
+        ));
+    }
+    output.push(String::from("</ul>"));
+}
+
+// Detail records are shown below their entry, only the entries are counted in the totals as in the trailer.
+// The trailer is checked against the totals of the file, the movements shown may be filtered.
+fn write_movements(output: &mut Vec<String>, coda: &Coda, file_totals: (u64, u64)) {
+    output.push(String::from("<h2>Movements</h2>"));
+    output.push(String::from("<table class=\"movements\">"));
+    output.push(String::from(
+        "<tr><th>Sequence</th><th>Entry date</th><th>Value date</th><th>Code</th><th>Counterparty</th>\
+         <th>Communication</th><th class=\"amount\">Debit</th><th class=\"amount\">Credit</th></tr>",
+    ));
+    for entry in coda.entries() {
+        for (i, movement) in entry.iter().enumerate() {
+            let class = if i == 0 { "entry" } else { "detail" };
+            output.push(format!(
+                "<tr class=\"{}\"><td>{}/{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{}</tr>",
+                class,
+                movement.sequence,
+                movement.detail_sequence,
+                movement.entry_date,
+                movement.value_date,
+                escape(&movement.transaction_code),
+                counterparty(movement),
+                communication(movement),
+                debit_credit_cells(movement)
+            ));
+        }
+    }
+    let (debit, credit) = coda.entry_totals();
+    output.push(format!(
+        "<tr class=\"total\"><td colspan=\"6\">Total{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td></tr>",
+        if (debit, credit) == file_totals { "" } else { " of the selected movements" },
+        format_amount(debit as i64, '.'),
+        format_amount(credit as i64, '.')
+    ));
+    let trailer = &coda.trailer;
+    let matches = (trailer.total_debit, trailer.total_credit) == file_totals;
+    output.push(format!(
+        "<tr class=\"{}\"><td colspan=\"6\">Trailer totals{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td></tr>",
+        if matches { "total" } else { "total mismatch" },
+        if matches { "" } else { " (do not match the movements)" },
+        format_amount(trailer.total_debit as i64, '.'),
+        format_amount(trailer.total_credit as i64, '.')
+    ));
+    output.push(String::from("</table>"));
+}
+
+fn write_information(output: &mut Vec<String>, coda: &Coda) {
+    if coda.information.is_empty() {
+        return;
+    }
+    output.push(String::from("<h2>Information</h2>"));
+    output.push(String::from("<table class=\"information\">"));
+    output.push(String::from(
+        "<tr><th>Sequence</th><th>Bank reference</th><th>Code</th><th>Communication</th></tr>",
+    ));
+    for information in &coda.information {
+        output.push(format!(
+            "<tr><td>{}/{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            information.sequence,
+            information.detail_sequence,
+            escape(information.bank_reference.trim()),
+            escape(&information.transaction_code),
+            escape(&information.communication)
+        ));
+    }
+    output.push(String::from("</table>"));
+}
+
+fn write_free_communications(output: &mut Vec<String>, coda: &Coda) {
+    if coda.free_communications.is_empty() {
+        return;
+    }
+    output.push(String::from("<h2>Free communications</h2>"));
+    for free_communication in &coda.free_communications {
+        output.push(format!("<p>{}</p>", escape(&free_communication.text)));
+    }
+}
+
+fn write_statement(output: &mut Vec<String>, coda: &Coda, file_totals: (u64, u64)) {
+    let old_balance = &coda.old_balance;
+    output.push(String::from("<section class=\"statement\">"));
+    output.push(format!(
+        "<h1>Statement {} - {} {}</h1>",
+        escape(&old_balance.old_sequence),
+        escape(old_balance.account.number()),
+        escape(old_balance.account.currency())
+    ));
+    write_summary(output, coda);
+    write_balances(output, coda);
+    write_findings(output, coda);
+    write_movements(output, coda, file_totals);
+    write_information(output, coda);
+    write_free_communications(output, coda);
+    output.push(String::from("</section>"));
+}
+
+// Self-contained document, one section per statement with a page break when printed.
+// file_totals are the entry totals (debit, credit) of each statement before the movements were filtered.
+pub fn to_html(coda_list: &[Coda], file_totals: &[(u64, u64)]) -> String {
+    let mut output = vec![
+        String::from("<!DOCTYPE html>"),
+        String::from("<html lang=\"en\">"),
+        String::from("<head>"),
+        String::from("<meta charset=\"utf-8\">"),
+        String::from("<title>CODA statements</title>"),
+        format!("<style>{}</style>", STYLE),
+        String::from("</head>"),
+        String::from("<body>"),
+    ];
+    for (coda, totals) in coda_list.iter().zip(file_totals) {
+        write_statement(&mut output, coda, *totals);
+    }
+    output.push(String::from("</body>"));
+    output.push(String::from("</html>"));
+    output.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod test_html {
+    use super::*;
+    use filter::MovementFilter;
+    use fixtures::parse_coda;
+
+    fn html(coda: Coda) -> String {
+        let totals = coda.entry_totals();
+        to_html(&[coda], &[totals])
+    }
+
+    #[test]
+    fn to_html_valid() {
+        let actual = html(parse_coda());
+
+        assert_eq!(actual.starts_with("<!DOCTYPE html>"), true, "Should be an html document");
+        assert_eq!(actual.contains("<h1>Statement 001 - 435000000080 EUR</h1>"), true, "Account header");
+        assert_eq!(
+            actual.contains("<tr><th>Closing balance 2006-12-07</th><td class=\"amount credit\">9405296.990</td></tr>"),
+            true,
+            "Closing balance"
+        );
+        assert_eq!(actual.contains("<p class=\"ok\">No findings</p>"), true, "No validation findings");
+        assert_eq!(actual.contains("NUMERO D&apos;OPERATI"), true, "Communication should be escaped");
+        assert_eq!(actual.contains("+++269/0211/57996+++"), true, "Structured reference");
+        assert_eq!(actual.contains("<h2>Information</h2>"), true, "Information records");
+        assert_eq!(actual.contains("<h2>Free communications</h2>"), true, "Free communications");
+        assert_eq!(actual.contains("<tr class=\"total mismatch\">"), false, "Totals should match the trailer");
+        assert_eq!(
+            actual.contains("<td class=\"amount\">3085871.600</td><td class=\"amount\">12491168.590</td>"),
+            true,
+            "Totals"
+        );
+    }
+
+    #[test]
+    fn to_html_findings() {
+        let mut coda = parse_coda();
+        coda.header.bic = String::from("<BAD>");
+
+        let actual = html(coda);
+
+        assert_eq!(actual.contains("<li class=\"error\">Error: header: "), true, "Error should be highlighted");
+        assert_eq!(actual.contains("&lt;BAD&gt;"), true, "Values should be escaped");
+    }
+
+    #[test]
+    fn to_html_trailer_mismatch() {
+        let mut coda = parse_coda();
+        coda.trailer.total_debit += 1;
+
+        let actual = html(coda);
+
+        assert_eq!(actual.contains("<tr class=\"total mismatch\">"), true, "Mismatch should be highlighted");
+    }
+
+    #[test]
+    fn to_html_filtered() {
+        let mut coda = parse_coda();
+        let totals = coda.entry_totals();
+        let mut filter = MovementFilter::default();
+        filter.add_expression("sign=debit").unwrap();
+        filter.apply(&mut coda);
+
+        let actual = to_html(&[coda], &[totals]);
+
+        assert_eq!(actual.contains("<tr class=\"total mismatch\">"), false, "Filtering is not a trailer mismatch");
+        assert_eq!(actual.contains("Total of the selected movements"), true, "Totals should be marked as filtered");
+    }
+}
@@ -0,0 +1,387 @@
+extern crate glob;
+
+use std::collections::HashSet;
+use std::fs::{read_dir, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+
+use archive::{holds_input, is_archive, read_archive};
+use coda::Coda;
+use input::glob::glob;
+use json;
+
+use errors::*;
+
+// Extensions of the coda files found in directories or by glob patterns, compared ignoring case
+const CODA_EXTENSIONS: [&str; 3] = ["cod", "cd2", "txt"];
+pub const JSON_EXTENSIONS: [&str; 2] = ["json", "ndjson"];
+
+// The header record of a coda file starts with 0000
+pub const CODA_START: &[u8] = b"0000";
+
+pub fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+pub fn is_coda_content(content: &[u8]) -> bool {
+    content.starts_with(CODA_START)
+}
+
+fn starts_as_coda(path: &Path) -> bool {
+    let mut start = [0u8; 4];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut start))
+        .map(|_| is_coda_content(&start))
+        .unwrap_or(false)
+}
+
+// Files found by discovery are kept on their extension, coda files must also start with a header record.
+// Zip and gzip archives are kept when they hold such a file.
+fn is_input_file(path: &Path, from_json: bool) -> bool {
+    if !path.is_file() {
+        return false;
+    }
+    if is_archive(path) {
+        holds_input(path, from_json)
+    } else if from_json {
+        has_extension(path, &JSON_EXTENSIONS)
+    } else {
+        has_extension(path, &CODA_EXTENSIONS) && starts_as_coda(path)
+    }
+}
+
+fn path_name(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+// Directories are walked recursively, entries in name order
+fn discover(path: &Path, from_json: bool, files: &mut Vec<String>) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = read_dir(path)
+            .chain_err(|| format!("Could not read directory [{}]", path.display()))?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<::std::io::Result<Vec<_>>>()
+            .chain_err(|| format!("Could not read directory [{}]", path.display()))?;
+        entries.sort();
+        for entry in entries {
+            discover(&entry, from_json, files)?;
+        }
+    } else if is_input_file(path, from_json) {
+        files.push(path_name(path));
+    }
+    Ok(())
+}
+
+fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+fn expand_input(arg: &str, from_json: bool, files: &mut Vec<String>) -> Result<()> {
+    if let Some(listfile) = arg.strip_prefix('@') {
+        let reader = BufReader::new(File::open(listfile).chain_err(|| format!("Could not open list file [{}]", listfile))?);
+        for line in reader.lines() {
+            let line = line.chain_err(|| format!("Could not read list file [{}]", listfile))?;
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                expand_input(line, from_json, files)?;
+            }
+        }
+    } else if is_pattern(arg) {
+        let mut found = Vec::new();
+        for path in glob(arg).chain_err(|| format!("Invalid pattern [{}]", arg))? {
+            let path = path.chain_err(|| format!("Could not read a match of [{}]", arg))?;
+            discover(&path, from_json, &mut found)?;
+        }
+        if found.is_empty() {
+            return Err(format!("No coda file matches [{}]", arg).into());
+        }
+        files.extend(found);
+    } else if Path::new(arg).is_dir() {
+        let mut found = Vec::new();
+        discover(Path::new(arg), from_json, &mut found)?;
+        if found.is_empty() {
+            return Err(format!("No coda file found in [{}]", arg).into());
+        }
+        files.extend(found);
+    } else {
+        // A file given by name is always read, parsing reports it when it is not a coda file
+        files.push(String::from(arg));
+    }
+    Ok(())
+}
+
+// Turns the input arguments into file names: files are kept, directories are searched recursively,
+// glob patterns are expanded and @file reads one argument per line (# starts a comment).
+// A file found more than once is only read the first time.
+pub fn expand_inputs(args: &[String], from_json: bool) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for arg in args {
+        expand_input(arg, from_json, &mut files)?;
+    }
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files)
+}
+
+fn parse_content(content: &[u8], encoding_label: &str, from_json: bool) -> Result<Vec<Coda>> {
+    if from_json {
+        json::from_json(&String::from_utf8_lossy(content))
+    } else {
+        Coda::parse_bytes(content, encoding_label).map(|coda| vec![coda])
+    }
+}
+
+// Reads the coda documents of an input file, each one with its source: the file name, or archive!entry
+// for the files of a zip or gzip archive
+pub fn read_input(filename: &str, encoding_label: &str, from_json: bool) -> Result<Vec<(String, Coda)>> {
+    if is_archive(Path::new(filename)) {
+        let mut codas = Vec::new();
+        for entry in read_archive(filename, from_json)? {
+            let parsed = parse_content(&entry.content, encoding_label, from_json)
+                .chain_err(|| format!("Unable to parse {}", entry.source))?;
+            codas.extend(parsed.into_iter().map(|coda| (entry.source.clone(), coda)));
+        }
+        return Ok(codas);
+    }
+    let parsed = if from_json {
+        json::read_json_file(filename)?
+    } else {
+        vec![Coda::parse(filename, encoding_label)?]
+    };
+    Ok(parsed.into_iter().map(|coda| (String::from(filename), coda)).collect())
+}
+
+// Reads the input files on a pool of threads, each one taking the next file to read until none is left.
+// The results are in the order of the files whatever the order in which the files are read.
+// jobs 0 uses one thread per processor.
+pub fn read_inputs(
+    filenames: &[String],
+    encoding_label: &str,
+    from_json: bool,
+    jobs: usize,
+    debug: bool,
+) -> Vec<Result<Vec<(String, Coda)>>> {
+    let jobs = match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    };
+    let next = AtomicUsize::new(0);
+    let mut results = filenames.iter().map(|_| None).collect::<Vec<_>>();
+    let (sender, receiver) = channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(filenames.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= filenames.len() {
+                    break;
+                }
+                if debug {
+                    println!("Parsing file: {}", filenames[i]);
+                }
+                let result = read_input(&filenames[i], encoding_label, from_json)
+                    .chain_err(|| format!("Unable to read {}", filenames[i]));
+                if sender.send((i, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        for (i, result) in receiver {
+            results[i] = Some(result);
+        }
+    });
+    results
+        .into_iter()
+        .zip(filenames)
+        .map(|(r, filename)| r.unwrap_or_else(|| Err(format!("Unable to read {}", filename).into())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_input {
+    extern crate flate2;
+    extern crate zip;
+
+    use std::env;
+    use std::fs::{copy, create_dir_all, remove_dir_all, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::*;
+    use self::flate2::write::GzEncoder;
+    use self::flate2::Compression;
+    use self::zip::write::FileOptions;
+    use self::zip::ZipWriter;
+
+    // A folder with coda files under several names, a non coda .txt file and other files
+    fn make_folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("coda-rs-input-{}-{}", name, ::std::process::id()));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(folder.join("2024/01")).unwrap();
+        copy("test-data/CODA.txt", folder.join("a.CD2")).unwrap();
+        copy("test-data/CODA.txt", folder.join("2024/01/b.cod")).unwrap();
+        copy("test-data/CODA.txt", folder.join("2024/c.txt")).unwrap();
+        copy("test-data/CODA.txt", folder.join("2024/d.pdf")).unwrap();
+        File::create(folder.join("2024/notes.txt"))
+            .unwrap()
+            .write_all(b"Not a coda file\n")
+            .unwrap();
+        folder
+    }
+
+    fn name(path: PathBuf) -> String {
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn expand_file() {
+        let actual = expand_inputs(&[String::from("some.pdf"), String::from("some.pdf")], false).unwrap();
+
+        assert_eq!(actual, vec!["some.pdf"], "Files should be kept once, whatever their name");
+    }
+
+    #[test]
+    fn expand_directory() {
+        let folder = make_folder("directory");
+
+        let actual = expand_inputs(&[name(folder.clone())], false).unwrap();
+
+        assert_eq!(
+            actual,
+            vec![
+                name(folder.join("2024/01/b.cod")),
+                name(folder.join("2024/c.txt")),
+                name(folder.join("a.CD2")),
+            ]
+        );
+        remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn expand_glob() {
+        let folder = make_folder("glob");
+
+        let actual = expand_inputs(&[name(folder.join("2024/*.txt"))], false).unwrap();
+
+        assert_eq!(actual, vec![name(folder.join("2024/c.txt"))], "Non coda files should be skipped");
+        assert_eq!(
+            expand_inputs(&[name(folder.join("*.xls"))], false).is_err(),
+            true,
+            "A pattern without match should be reported"
+        );
+        remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn expand_listfile() {
+        let folder = make_folder("listfile");
+        let listfile = folder.join("files.lst");
+        File::create(&listfile)
+            .unwrap()
+            .write_all(format!("# January\n{}\n\n{}\n", name(folder.join("2024/01")), name(folder.join("a.CD2"))).as_bytes())
+            .unwrap();
+
+        let actual = expand_inputs(&[format!("@{}", name(listfile))], false).unwrap();
+
+        assert_eq!(actual, vec![name(folder.join("2024/01/b.cod")), name(folder.join("a.CD2"))]);
+        assert_eq!(expand_inputs(&[String::from("@missing.lst")], false).is_err(), true);
+        remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn expand_directory_archives() {
+        let folder = env::temp_dir().join(format!("coda-rs-input-archives-{}", ::std::process::id()));
+        let _ = remove_dir_all(&folder);
+        create_dir_all(&folder).unwrap();
+        copy("test-data/CODA.txt", folder.join("a.CD2")).unwrap();
+        let mut zip = ZipWriter::new(File::create(folder.join("photos.zip")).unwrap());
+        zip.start_file("beach.jpg", FileOptions::default()).unwrap();
+        zip.write_all(b"\xff\xd8\xff\xe0 not a statement").unwrap();
+        zip.finish().unwrap();
+        let mut zip = ZipWriter::new(File::create(folder.join("statements.zip")).unwrap());
+        zip.start_file("b.CD2", FileOptions::default()).unwrap();
+        zip.write_all(&::std::fs::read("test-data/CODA.txt").unwrap()).unwrap();
+        zip.finish().unwrap();
+        let mut gzip = GzEncoder::new(File::create(folder.join("notes.gz")).unwrap(), Compression::default());
+        gzip.write_all(b"Not a coda file\n").unwrap();
+        gzip.finish().unwrap();
+        let mut gzip = GzEncoder::new(File::create(folder.join("c.CD2.gz")).unwrap(), Compression::default());
+        gzip.write_all(&::std::fs::read("test-data/CODA.txt").unwrap()).unwrap();
+        gzip.finish().unwrap();
+
+        let actual = expand_inputs(&[name(folder.clone())], false).unwrap();
+
+        assert_eq!(
+            actual,
+            vec![name(folder.join("a.CD2")), name(folder.join("c.CD2.gz")), name(folder.join("statements.zip"))],
+            "Archives without a coda file should be skipped"
+        );
+        let read = read_inputs(&actual, "latin1", false, 2, false);
+        assert_eq!(read.iter().all(|r| r.is_ok()), true, "The discovered files should all be read");
+        assert_eq!(
+            expand_inputs(&[name(folder.clone())], true).unwrap_err().to_string(),
+            format!("No coda file found in [{}]", name(folder.clone())),
+            "No archive holds a json file"
+        );
+        let explicit = expand_inputs(&[name(folder.join("photos.zip"))], false).unwrap();
+        assert_eq!(
+            read_input(&explicit[0], "latin1", false).is_err(),
+            true,
+            "An archive named on the command line should be reported"
+        );
+        remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn read_inputs_undecodable() {
+        let folder = make_folder("undecodable");
+        let bad = folder.join("bad.cod");
+        let mut content = ::std::fs::read("test-data/CODA.txt").unwrap();
+        content.extend_from_slice(b"\xff\xfe\n");
+        File::create(&bad).unwrap().write_all(&content).unwrap();
+        let filenames = vec![name(folder.join("a.CD2")), name(bad), name(folder.join("2024/c.txt"))];
+
+        let actual = read_inputs(&filenames, "utf-8", false, 2, false);
+
+        assert_eq!(actual.len(), 3);
+        assert_eq!(actual[0].is_ok(), true);
+        assert_eq!(actual[1].is_err(), true, "The undecodable file should be reported as an error");
+        assert_eq!(actual[2].is_ok(), true);
+        let causes = actual[1].as_ref().err().unwrap().iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(causes.iter().any(|c| c.contains("Could not decode the content as utf-8")), true);
+
+        let actual = read_inputs(&filenames[..1], "bogus", false, 1, false);
+        assert_eq!(actual[0].is_err(), true, "An unknown encoding should be reported as an error");
+        remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn read_inputs_in_order() {
+        let folder = make_folder("read");
+        let mut filenames = expand_inputs(&[name(folder.clone())], false).unwrap();
+        filenames.insert(1, String::from("test-data/CODA-bad.txt"));
+
+        let actual = read_inputs(&filenames, "latin1", false, 3, false);
+
+        assert_eq!(actual.len(), 4);
+        assert_eq!(actual[1].is_err(), true, "The error should stay with its file");
+        let sources = actual
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .flat_map(|codas| codas.iter().map(|&(ref source, _)| source.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![filenames[0].clone(), filenames[2].clone(), filenames[3].clone()],
+            "Files should be in the order of the arguments"
+        );
+        remove_dir_all(folder).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+extern crate serde;
+
+use std::fmt;
+
+use iso20022::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use errors::*;
+
+// Declare an enum over an ISO 20022 external code list. Each variant carries its 4 letter code and its description,
+// unknown codes are kept in `Other`. Values (de)serialize as their code so the json output keeps the raw CODA value.
+macro_rules! iso_code_enum {
+    ($name:ident { $($variant:ident => ($code:expr, $description:expr),)* }) => {
+        #[derive(PartialEq, Debug, Clone)]
+        pub enum $name {
+            $($variant,)*
+            Other(String),
+        }
+
+        impl $name {
+            pub fn from_code(code: &str) -> $name {
+                match code {
+                    $($code => $name::$variant,)*
+                    _ => $name::Other(String::from(code)),
+                }
+            }
+
+            // Blank zones mean the code is absent
+            pub fn parse(s: &str) -> Result<Option<$name>> {
+                match s.trim() {
+                    "" => Ok(None),
+                    code => Ok(Some($name::from_code(code))),
+                }
+            }
+
+            pub fn code(&self) -> &str {
+                match *self {
+                    $($name::$variant => $code,)*
+                    $name::Other(ref code) => code,
+                }
+            }
+
+            pub fn description(&self) -> &str {
+                match *self {
+                    $($name::$variant => $description,)*
+                    $name::Other(_) => "Unknown code",
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.code())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                s.serialize_str(self.code())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(d: D) -> ::std::result::Result<$name, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let code = String::deserialize(d)?;
+                Ok($name::from_code(&code))
+            }
+        }
+    };
+}
+
+// ExternalReturnReason1Code, as used in the reason zone of SEPA R-transactions (record 2.2)
+iso_code_enum!(ReturnReason {
+    IncorrectAccountNumber => ("AC01", "Account identifier incorrect (invalid IBAN)"),
+    InvalidCreditorAccountNumber => ("AC03", "Creditor account identifier invalid"),
+    ClosedAccountNumber => ("AC04", "Account closed"),
+    BlockedAccount => ("AC06", "Account blocked, direct debit not allowed"),
+    InvalidDebtorAccountType => ("AC13", "Debtor account is a consumer account"),
+    TransactionForbidden => ("AG01", "Transaction forbidden on this type of account"),
+    InvalidBankOperationCode => ("AG02", "Operation code or transaction code incorrect"),
+    IncorrectAgent => ("AGNT", "Agent in the payment workflow is incorrect"),
+    InsufficientFunds => ("AM04", "Insufficient funds"),
+    Duplication => ("AM05", "Duplicate collection"),
+    WrongAmount => ("AM09", "Amount received is not the amount agreed or expected"),
+    UnrecognisedInitiatingParty => ("BE05", "Creditor identifier incorrect"),
+    CreditorBankNotRegistered => ("CNOR", "Creditor bank is not registered under this BIC in the CSM"),
+    IncorrectCurrency => ("CURR", "Currency of the payment is incorrect"),
+    RequestedByCustomer => ("CUST", "Requested by customer"),
+    DebtorBankNotRegistered => ("DNOR", "Debtor bank is not registered under this BIC in the CSM"),
+    DuplicatePayment => ("DUPL", "Payment is a duplicate of another payment"),
+    SettlementFailed => ("ED05", "Settlement of the transaction has failed"),
+    InvalidFileFormat => ("FF01", "Operation or transaction code incorrect, invalid file format"),
+    FollowingCancellationRequest => ("FOCR", "Return following a cancellation request"),
+    FraudulentOrigin => ("FRAD", "Fraudulent originated payment"),
+    NoMandate => ("MD01", "No valid mandate"),
+    MissingMandatoryMandateInformation => ("MD02", "Mandate data missing or incorrect"),
+    RefundRequestByEndCustomer => ("MD06", "Refund request by the debtor"),
+    EndCustomerDeceased => ("MD07", "Debtor deceased"),
+    NotSpecifiedReasonCustomerGenerated => ("MS02", "Refusal by the debtor, reason not specified"),
+    NotSpecifiedReasonAgentGenerated => ("MS03", "Reason not specified by the agent"),
+    BankIdentifierIncorrect => ("RC01", "Bank identifier (BIC) incorrect"),
+    MissingDebtorAccountOrIdentification => ("RR01", "Debtor account or identification missing (regulatory)"),
+    MissingDebtorNameOrAddress => ("RR02", "Debtor name or address missing (regulatory)"),
+    MissingCreditorNameOrAddress => ("RR03", "Creditor name or address missing (regulatory)"),
+    RegulatoryReason => ("RR04", "Regulatory reason"),
+    SpecificServiceOfferedByDebtorAgent => ("SL01", "Specific service offered by the debtor bank"),
+    TechnicalProblem => ("TECH", "Technical problem resulting in an erroneous transaction"),
+    UnduePayment => ("UPAY", "Payment is not justified"),
+});
+
+// ExternalCategoryPurpose1Code (record 2.2)
+iso_code_enum!(CategoryPurpose {
+    Bonus => ("BONU", "Bonus payment"),
+    CashManagementTransfer => ("CASH", "Cash management transfer"),
+    CardBulkClearing => ("CBLK", "Card bulk clearing"),
+    CreditCardPayment => ("CCRD", "Credit card payment"),
+    TradeSettlementPayment => ("CORT", "Trade settlement payment"),
+    DebitCardPayment => ("DCRD", "Debit card payment"),
+    Dividend => ("DIVI", "Dividend"),
+    DeliverAgainstPayment => ("DVPM", "Deliver against payment"),
+    Epayment => ("EPAY", "Payment initiated through internet banking"),
+    FeeCollectionAndInterest => ("FCIN", "Fee collection and interest"),
+    FeeCollection => ("FCOL", "Fee collection"),
+    PersonToPersonPayment => ("GP2P", "General person-to-person payment"),
+    GovernmentPayment => ("GOVT", "Government payment"),
+    Hedging => ("HEDG", "Hedging"),
+    IrrevocableCreditCardPayment => ("ICCP", "Irrevocable credit card payment"),
+    IrrevocableDebitCardPayment => ("IDCP", "Irrevocable debit card payment"),
+    IntraCompanyPayment => ("INTC", "Intra-company payment"),
+    Interest => ("INTE", "Interest"),
+    LockboxTransactions => ("LBOX", "Lockbox transactions"),
+    Loan => ("LOAN", "Loan"),
+    MobileP2BPayment => ("MP2B", "Mobile person-to-business payment"),
+    MobileP2PPayment => ("MP2P", "Mobile person-to-person payment"),
+    OtherPayment => ("OTHR", "Other payment"),
+    PensionPayment => ("PENS", "Pension payment"),
+    Represented => ("RPRE", "Represented direct debit"),
+    ReimbursementReceivedCreditTransfer => ("RRCT", "Reimbursement of a received credit transfer"),
+    ReceiveAgainstPayment => ("RVPM", "Receive against payment"),
+    SalaryPayment => ("SALA", "Salary payment"),
+    Securities => ("SECU", "Securities"),
+    SocialSecurityBenefit => ("SSBE", "Social security benefit"),
+    SupplierPayment => ("SUPP", "Supplier payment"),
+    CashManagementSweepAccount => ("SWEP", "Cash management sweep account"),
+    TaxPayment => ("TAXS", "Tax payment"),
+    CashManagementTopAccount => ("TOPG", "Cash management top account"),
+    Trade => ("TRAD", "Trade services"),
+    TreasuryPayment => ("TREA", "Treasury payment"),
+    ValueAddedTaxPayment => ("VATX", "Value added tax payment"),
+    WithHolding => ("WHLD", "Withholding tax"),
+    CashManagementZeroBalanceAccount => ("ZABA", "Cash management zero balance account"),
+});
+
+// ExternalPurpose1Code (record 2.2). Only a subset of the list is named: the codes seen on Belgian statements
+// for salaries, suppliers, taxes, utilities, insurance and loans. The other codes of the list are valid
+// and kept as is in `Other`, they are filtered and grouped by their code like the named ones.
+iso_code_enum!(Purpose {
+    AccountManagement => ("ACCT", "Account management"),
+    AdvancePayment => ("ADVA", "Advance payment"),
+    AgriculturalTransfer => ("AGRT", "Agricultural transfer"),
+    ChildBenefit => ("BECH", "Child benefit"),
+    UnemploymentDisabilityBenefit => ("BENE", "Unemployment or disability benefit"),
+    BonusPayment => ("BONU", "Bonus payment"),
+    CashManagementTransfer => ("CASH", "Cash management transfer"),
+    CableTvBill => ("CBTV", "Cable TV bill"),
+    CharityPayment => ("CHAR", "Charity payment"),
+    CollectionPayment => ("COLL", "Collection payment"),
+    CommercialPayment => ("COMC", "Commercial payment"),
+    Commission => ("COMM", "Commission"),
+    Dividend => ("DIVD", "Dividend"),
+    ElectricityBill => ("ELEC", "Electricity bill"),
+    EstateTax => ("ESTX", "Estate tax"),
+    GasBill => ("GASB", "Gas bill"),
+    PurchaseSaleOfGoods => ("GDDS", "Purchase or sale of goods"),
+    GovernmentPayment => ("GOVT", "Government payment"),
+    HealthInsurance => ("HLTI", "Health insurance"),
+    IncomeTax => ("INCT", "Income tax"),
+    InsurancePremium => ("INSU", "Insurance premium"),
+    IntraCompanyPayment => ("INTC", "Intra-company payment"),
+    Interest => ("INTE", "Interest"),
+    InvestmentAndSecurities => ("INVS", "Investment and securities"),
+    InvoicePayment => ("IVPT", "Invoice payment"),
+    LifeInsurance => ("LIFI", "Life insurance"),
+    Loan => ("LOAN", "Loan"),
+    OtherPurpose => ("OTHR", "Other"),
+    Payroll => ("PAYR", "Payroll"),
+    PensionPayment => ("PENS", "Pension payment"),
+    TelephoneBill => ("PHON", "Telephone bill"),
+    Refund => ("REFU", "Refund"),
+    Rent => ("RENT", "Rent"),
+    RecurringInstallmentPayment => ("RINP", "Recurring installment payment"),
+    SalaryPayment => ("SALA", "Salary payment"),
+    PurchaseSaleOfServices => ("SCVE", "Purchase or sale of services"),
+    SocialSecurityBenefit => ("SSBE", "Social security benefit"),
+    Study => ("STDY", "Study"),
+    SupplierPayment => ("SUPP", "Supplier payment"),
+    TaxPayment => ("TAXS", "Tax payment"),
+    TelephoneInitiatedTransaction => ("TELI", "Telephone initiated transaction"),
+    TradeServices => ("TRAD", "Trade services"),
+    TreasuryPayment => ("TREA", "Treasury payment"),
+    ValueAddedTaxPayment => ("VATX", "Value added tax payment"),
+    WaterBill => ("WTER", "Water bill"),
+});
+
+#[cfg(test)]
+mod test_return_reason {
+    use super::*;
+
+    #[test]
+    fn parse_return_reason_valid() {
+        let actual = ReturnReason::parse("AM04");
+        assert_eq!(actual.is_ok(), true, "'AM04' should be ok");
+        assert_eq!(
+            actual.unwrap(),
+            Some(ReturnReason::InsufficientFunds),
+            "'AM04' should be InsufficientFunds"
+        );
+    }
+
+    #[test]
+    fn parse_return_reason_blank() {
+        let actual = ReturnReason::parse("    ");
+        assert_eq!(actual.is_ok(), true, "'    ' should be ok");
+        assert_eq!(actual.unwrap(), None, "'    ' should be None");
+    }
+
+    #[test]
+    fn parse_return_reason_other() {
+        let actual = ReturnReason::parse("XY99").unwrap().unwrap();
+        assert_eq!(actual, ReturnReason::Other(String::from("XY99")));
+        assert_eq!(actual.code(), "XY99");
+        assert_eq!(actual.description(), "Unknown code");
+    }
+
+    #[test]
+    fn return_reason_description() {
+        assert_eq!(ReturnReason::RefundRequestByEndCustomer.code(), "MD06");
+        assert_eq!(
+            ReturnReason::RefundRequestByEndCustomer.description(),
+            "Refund request by the debtor"
+        );
+    }
+
+    #[test]
+    fn return_reason_serialize_as_code() {
+        let actual = ::serde_json::to_string(&ReturnReason::NoMandate);
+        assert_eq!(actual.unwrap(), "\"MD01\"");
+    }
+}
+
+#[cfg(test)]
+mod test_purpose {
+    extern crate serde_json;
+
+    use super::*;
+
+    #[test]
+    fn purpose_serde_roundtrip() {
+        let codes = vec![Purpose::PurchaseSaleOfGoods, Purpose::Other(String::from("ZZZZ"))];
+
+        let j = serde_json::to_string(&codes).unwrap();
+        let actual: Vec<Purpose> = serde_json::from_str(&j).unwrap();
+
+        assert_eq!(j, "[\"GDDS\",\"ZZZZ\"]");
+        assert_eq!(actual, codes);
+    }
+
+    #[test]
+    fn parse_category_purpose_valid() {
+        let actual = CategoryPurpose::parse("SALA");
+        assert_eq!(actual.is_ok(), true, "'SALA' should be ok");
+        assert_eq!(actual.unwrap(), Some(CategoryPurpose::SalaryPayment));
+        assert_eq!(CategoryPurpose::SupplierPayment.description(), "Supplier payment");
+    }
+
+    #[test]
+    fn parse_purpose_valid() {
+        let actual = Purpose::parse("ELEC");
+        assert_eq!(actual.is_ok(), true, "'ELEC' should be ok");
+        assert_eq!(actual.unwrap(), Some(Purpose::ElectricityBill));
+    }
+
+    #[test]
+    fn parse_purpose_other() {
+        let actual = Purpose::parse("ZZZZ").unwrap().unwrap();
+        assert_eq!(actual, Purpose::Other(String::from("ZZZZ")));
+        assert_eq!(actual.code(), "ZZZZ");
+    }
+}
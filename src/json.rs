@@ -3,36 +3,378 @@ extern crate encoding;
 extern crate serde;
 extern crate serde_json;
 
+use std::cell::Cell;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use std::fs::File;
+use std::io::Read;
+
+use self::serde::ser::{SerializeStruct, SerializeStructVariant};
+use self::serde::{Serialize, Serializer};
+use self::serde_json::Value;
+
+use coda::{Account, Coda};
+use utils::{format_amount, signed_amount, Sign};
+
 use errors::*;
-use coda::Coda;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum JsonFormat {
+    // One pretty printed document per coda file
+    Pretty,
+    // A single json array holding all coda files
+    Array,
+    // One compact document per line (newline delimited json)
+    Ndjson,
+}
+
+impl FromStr for JsonFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<JsonFormat, String> {
+        match s {
+            "pretty" => Ok(JsonFormat::Pretty),
+            "array" => Ok(JsonFormat::Array),
+            "ndjson" => Ok(JsonFormat::Ndjson),
+            _ => Err(format!("Invalid json format [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum KeyCase {
+    Snake,
+    Camel,
+}
+
+impl FromStr for KeyCase {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<KeyCase, String> {
+        match s {
+            "snake" => Ok(KeyCase::Snake),
+            "camel" => Ok(KeyCase::Camel),
+            _ => Err(format!("Invalid json key case [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AccountTagging {
+    // {"IBANBelgianAccountNumber": {"number": ..., "currency": ...}}
+    External,
+    // {"type": "iban_belgian", "number": ..., "currency": ...}
+    Internal,
+}
+
+impl FromStr for AccountTagging {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<AccountTagging, String> {
+        match s {
+            "external" => Ok(AccountTagging::External),
+            "internal" => Ok(AccountTagging::Internal),
+            _ => Err(format!("Invalid json account tagging [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DateFormat {
+    // yyyy-mm-dd
+    Iso,
+    // dd/mm/yyyy
+    Dmy,
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<DateFormat, String> {
+        match s {
+            "iso" => Ok(DateFormat::Iso),
+            "dmy" => Ok(DateFormat::Dmy),
+            _ => Err(format!("Invalid json date format [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AmountFormat {
+    // Integer in thousandths as in the coda file, movement amounts keep the sign as first digit
+    Integer,
+    // Decimal string, negative for debits and debit balances, ie "-2578.250"
+    Decimal,
+}
+
+impl FromStr for AmountFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<AmountFormat, String> {
+        match s {
+            "integer" => Ok(AmountFormat::Integer),
+            "decimal" => Ok(AmountFormat::Decimal),
+            _ => Err(format!("Invalid json amount format [{}]", s)),
+        }
+    }
+}
+
+// Shape of the written json, the default is the shape described by the json schema and read by --from-json
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct JsonShape {
+    pub key_case: KeyCase,
+    pub account_tagging: AccountTagging,
+    pub date_format: DateFormat,
+    pub amount_format: AmountFormat,
+}
+
+impl Default for JsonShape {
+    fn default() -> JsonShape {
+        JsonShape {
+            key_case: KeyCase::Snake,
+            account_tagging: AccountTagging::External,
+            date_format: DateFormat::Iso,
+            amount_format: AmountFormat::Integer,
+        }
+    }
+}
+
+fn camel_case(key: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+impl JsonShape {
+    pub fn is_default(&self) -> bool {
+        *self == JsonShape::default()
+    }
+
+    fn camel_case_keys(&self, value: Value) -> Value {
+        match value {
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .map(|(key, value)| (camel_case(&key), self.camel_case_keys(value)))
+                    .collect(),
+            ),
+            Value::Array(values) => Value::Array(values.into_iter().map(|v| self.camel_case_keys(v)).collect()),
+            value => value,
+        }
+    }
+
+    // Dates, amounts and accounts are shaped by their serializers, the keys are renamed afterwards
+    pub fn to_value<T: Serialize>(&self, value: &T) -> Result<Value> {
+        let previous = SHAPE.with(|shape| shape.replace(*self));
+        let value = serde_json::to_value(value);
+        SHAPE.with(|shape| shape.set(previous));
+        let value = value.chain_err(|| "Unable to generate json")?;
+        Ok(match self.key_case {
+            KeyCase::Snake => value,
+            KeyCase::Camel => self.camel_case_keys(value),
+        })
+    }
+}
+
+thread_local! {
+    // Shape of the json being written by JsonShape::to_value, the default one otherwise
+    static SHAPE: Cell<JsonShape> = Cell::new(JsonShape::default());
+}
+
+fn current_shape() -> JsonShape {
+    SHAPE.with(|shape| shape.get())
+}
 
 pub mod date_serde {
     use chrono::NaiveDate;
-    use json::serde::Serializer;
+    use json::serde::{Deserialize, Deserializer, Serializer};
+    use json::serde::de::Error;
+    use json::{current_shape, DateFormat};
 
     pub fn serialize<S>(date: &NaiveDate, s: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        return s.serialize_str(&format!("{}", date.format("%Y-%m-%d")));
+        match current_shape().date_format {
+            DateFormat::Iso => s.serialize_str(&format!("{}", date.format("%Y-%m-%d"))),
+            DateFormat::Dmy => s.serialize_str(&format!("{}", date.format("%d/%m/%Y"))),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(D::Error::custom)
+    }
+}
+
+// Amounts in thousandths, written as integers or as decimal strings depending on the shape
+pub mod amount_serde {
+    use coda::split_movement_amount;
+    use json::serde::Serializer;
+    use json::{current_shape, AmountFormat};
+    use utils::{format_amount, signed_amount};
+
+    pub fn serialize<S>(amount: &u64, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match current_shape().amount_format {
+            AmountFormat::Integer => s.serialize_u64(*amount),
+            AmountFormat::Decimal => s.serialize_str(&format_amount(*amount as i64, '.')),
+        }
+    }
+
+    pub fn serialize_signed<S>(amount: &i64, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match current_shape().amount_format {
+            AmountFormat::Integer => s.serialize_i64(*amount),
+            AmountFormat::Decimal => s.serialize_str(&format_amount(*amount, '.')),
+        }
+    }
+
+    // Movement amounts keep the sign of the coda file as first digit, the decimal string is negative for debits
+    pub fn serialize_movement<S>(amount: &u64, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (sign, unsigned) = split_movement_amount(*amount);
+        match current_shape().amount_format {
+            AmountFormat::Integer => s.serialize_u64(*amount),
+            AmountFormat::Decimal => s.serialize_str(&format_amount(signed_amount(&sign, unsigned), '.')),
+        }
+    }
+}
+
+// Balances and the amount of the movement rows are unsigned, their sign is in the field next to them.
+// The decimal string is negative for debits.
+pub struct SignedAmount<'a>(pub &'a Sign, pub u64);
+
+impl<'a> Serialize for SignedAmount<'a> {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match current_shape().amount_format {
+            AmountFormat::Integer => s.serialize_u64(self.1),
+            AmountFormat::Decimal => s.serialize_str(&format_amount(signed_amount(self.0, self.1), '.')),
+        }
+    }
+}
+
+// A signed amount of the hand written serializers, written as the amount_serde::serialize_signed fields
+pub struct JsonAmount(pub i64);
+
+impl Serialize for JsonAmount {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        amount_serde::serialize_signed(&self.0, s)
+    }
+}
+
+// A date of the hand written serializers, written as the date_serde fields
+pub struct JsonDate<'a>(pub &'a NaiveDate);
+
+impl<'a> Serialize for JsonDate<'a> {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        date_serde::serialize(self.0, s)
     }
+}
 
-    // pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate>, D::Error>
-    // where
-    //     D: Deserializer<'de>,
-    // {
-    //     let s: Option<String> = Option::deserialize(deserializer)?;
-    //     if let Some(s) = s {
-    //         return Ok(Some(NaiveDate::parse_from_str(&s, "%Y-%m-%d")
-    //             .map_err(serde::de::Error::custom)?));
-    //     }
+// Accounts are written as their variant name around the fields (read back by --from-json),
+// or with a type field next to the fields
+impl Serialize for Account {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (index, variant, tag, country) = match *self {
+            Account::BelgianAccountNumber { ref country, .. } => (0, "BelgianAccountNumber", "belgian", Some(country)),
+            Account::ForeignAccountNumber { .. } => (1, "ForeignAccountNumber", "foreign", None),
+            Account::IBANBelgianAccountNumber { .. } => (2, "IBANBelgianAccountNumber", "iban_belgian", None),
+            Account::IBANForeignAccountNumber { .. } => (3, "IBANForeignAccountNumber", "iban_foreign", None),
+        };
+        let len = if country.is_some() { 3 } else { 2 };
+        match current_shape().account_tagging {
+            AccountTagging::External => {
+                let mut fields = s.serialize_struct_variant("Account", index, variant, len)?;
+                fields.serialize_field("number", self.number())?;
+                fields.serialize_field("currency", self.currency())?;
+                if let Some(country) = country {
+                    fields.serialize_field("country", country)?;
+                }
+                fields.end()
+            }
+            AccountTagging::Internal => {
+                let mut fields = s.serialize_struct("Account", len + 1)?;
+                fields.serialize_field("type", tag)?;
+                fields.serialize_field("number", self.number())?;
+                fields.serialize_field("currency", self.currency())?;
+                if let Some(country) = country {
+                    fields.serialize_field("country", country)?;
+                }
+                fields.end()
+            }
+        }
+    }
+}
+
+pub fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string_pretty(value).chain_err(|| "Unable to generate json file")?)
+}
 
-    //     Ok(None)
-    // }
+pub fn to_json_compact<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value).chain_err(|| "Unable to generate json file")?)
 }
 
-pub fn to_json(coda: &Coda) -> Result<String> {
-    Ok(serde_json::to_string_pretty(coda).chain_err(|| "Unable to generate json file")?)
+pub fn to_json_array<T: Serialize>(values: &[T]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(values).chain_err(|| "Unable to generate json file")?)
+}
+
+// Reads back the json written by coda-rs in any of its layouts: pretty documents, one array or ndjson
+pub fn from_json(json: &str) -> Result<Vec<Coda>> {
+    let mut coda_list = Vec::new();
+    for value in serde_json::Deserializer::from_str(json).into_iter::<Value>() {
+        match value.chain_err(|| "Invalid json")? {
+            Value::Array(values) => {
+                for value in values {
+                    coda_list.push(serde_json::from_value(value).chain_err(|| "Json is not a coda document")?);
+                }
+            }
+            value => coda_list.push(serde_json::from_value(value).chain_err(|| "Json is not a coda document")?),
+        }
+    }
+    Ok(coda_list)
+}
+
+pub fn read_json_file(filename: &str) -> Result<Vec<Coda>> {
+    let mut json = String::new();
+    File::open(filename)
+        .chain_err(|| format!("Unable to open {}", filename))?
+        .read_to_string(&mut json)
+        .chain_err(|| format!("Unable to read {}", filename))?;
+    from_json(&json).chain_err(|| format!("Unable to read coda json from {}", filename))
 }
 
 #[cfg(test)]
@@ -41,11 +383,14 @@ mod test_json {
 
     use super::*;
     use coda::*;
+    use movements::movement_rows;
+    use stats::account_stats;
     use utils::*;
+    use fixtures::parse_coda;
 
-    #[test]
-    fn to_json_valid() {
-        let coda = Coda {
+    fn make_coda() -> Coda {
+        Coda {
+            schema_version: 1,
             header: Header {
                 creation_date: NaiveDate::from_ymd(2018, 4, 2),
                 bank_id: String::from("bank_id"),
@@ -85,10 +430,197 @@ mod test_json {
                 total_debit: 4321000,
                 total_credit: 123400,
             },
-        };
+        }
+    }
+
+    #[test]
+    fn to_json_valid() {
+        let coda = make_coda();
 
         let j = to_json(&coda);
 
         assert_eq!(j.is_ok(), true, "to_json should be ok");
     }
+
+    #[test]
+    fn to_json_compact_valid() {
+        let coda = make_coda();
+
+        let j = to_json_compact(&coda);
+
+        assert_eq!(j.is_ok(), true, "to_json_compact should be ok");
+        assert_eq!(j.unwrap().contains('\n'), false, "Compact json should be on one line");
+    }
+
+    #[test]
+    fn to_json_array_valid() {
+        let coda_list = vec![make_coda(), make_coda()];
+
+        let j = to_json_array(&coda_list);
+
+        assert_eq!(j.is_ok(), true, "to_json_array should be ok");
+        let value: serde_json::Value = serde_json::from_str(&j.unwrap()).unwrap();
+        assert_eq!(value.as_array().map(|a| a.len()), Some(2), "Array should hold 2 documents");
+    }
+
+    #[test]
+    fn from_json_roundtrip() {
+        let coda = parse_coda();
+        let j = to_json(&coda).unwrap();
+
+        let actual = from_json(&j);
+
+        assert_eq!(actual.is_ok(), true, "from_json should be ok");
+        assert_eq!(actual.unwrap(), vec![coda], "Coda should be the same after a roundtrip");
+    }
+
+    #[test]
+    fn from_json_layouts() {
+        let coda_list = vec![make_coda(), make_coda()];
+        let pretty = format!("{}\n{}\n", to_json(&coda_list[0]).unwrap(), to_json(&coda_list[1]).unwrap());
+        let array = to_json_array(&coda_list).unwrap();
+        let ndjson = format!(
+            "{}\n{}\n",
+            to_json_compact(&coda_list[0]).unwrap(),
+            to_json_compact(&coda_list[1]).unwrap()
+        );
+
+        assert_eq!(from_json(&pretty).unwrap(), coda_list, "Pretty documents");
+        assert_eq!(from_json(&array).unwrap(), coda_list, "Array");
+        assert_eq!(from_json(&ndjson).unwrap(), coda_list, "Ndjson");
+    }
+
+    #[test]
+    fn from_json_invalid() {
+        assert_eq!(from_json("{\"header\": 1}").is_ok(), false, "Not a coda document");
+        assert_eq!(from_json("[1, 2").is_ok(), false, "Invalid json");
+    }
+
+    #[test]
+    fn date_serde_invalid() {
+        let j = to_json(&make_coda()).unwrap().replace("2018-04-02", "02/04/2018");
+
+        assert_eq!(from_json(&j).is_ok(), false, "Dates should be yyyy-mm-dd");
+    }
+
+    #[test]
+    fn json_format_from_str() {
+        assert_eq!("pretty".parse::<JsonFormat>(), Ok(JsonFormat::Pretty));
+        assert_eq!("array".parse::<JsonFormat>(), Ok(JsonFormat::Array));
+        assert_eq!("ndjson".parse::<JsonFormat>(), Ok(JsonFormat::Ndjson));
+        assert_eq!("xml".parse::<JsonFormat>().is_err(), true, "xml should not be ok");
+    }
+
+    fn shape(key_case: KeyCase, account_tagging: AccountTagging, date_format: DateFormat, amount_format: AmountFormat) -> JsonShape {
+        JsonShape {
+            key_case,
+            account_tagging,
+            date_format,
+            amount_format,
+        }
+    }
+
+    #[test]
+    fn json_shape_default() {
+        let coda = parse_coda();
+        let expected = serde_json::to_value(&coda).unwrap();
+
+        let actual = JsonShape::default().to_value(&coda).unwrap();
+
+        assert_eq!(actual, expected, "Default shape should not change the json");
+    }
+
+    #[test]
+    fn json_shape_camel_case() {
+        let shape = shape(KeyCase::Camel, AccountTagging::External, DateFormat::Iso, AmountFormat::Integer);
+
+        let actual = shape.to_value(&make_coda()).unwrap();
+
+        assert_eq!(actual["oldBalance"]["oldBalanceDate"], json!("2018-04-01"));
+        assert_eq!(actual["trailer"]["totalDebit"], json!(4321000));
+        assert_eq!(actual["header"].get("creation_date"), None, "Snake case keys should be renamed");
+        assert_eq!(
+            actual["oldBalance"]["account"]["IBANBelgianAccountNumber"]["number"],
+            json!("BE3333"),
+            "Account variants should not be renamed"
+        );
+    }
+
+    #[test]
+    fn json_shape_internal_account() {
+        let shape = shape(KeyCase::Snake, AccountTagging::Internal, DateFormat::Iso, AmountFormat::Integer);
+
+        let actual = shape.to_value(&make_coda()).unwrap();
+
+        assert_eq!(
+            actual["old_balance"]["account"],
+            json!({"type": "iban_belgian", "number": "BE3333", "currency": "EUR"})
+        );
+    }
+
+    #[test]
+    fn json_shape_dates_and_amounts() {
+        let shape = shape(KeyCase::Snake, AccountTagging::External, DateFormat::Dmy, AmountFormat::Decimal);
+        let coda = parse_coda();
+
+        let actual = shape.to_value(&coda).unwrap();
+
+        assert_eq!(actual["header"]["creation_date"], json!("06/12/2006"));
+        assert_eq!(actual["movements"][0]["value_date"], json!("06/12/2006"));
+        assert_eq!(actual["movements"][0]["amount"], json!("-2578.250"), "Movement amounts should be signed");
+        assert_eq!(actual["new_balance"]["new_balance"], json!("9405296.990"));
+        assert_eq!(actual["new_balance"]["new_balance_sign"], json!("Credit"));
+        assert_eq!(actual["trailer"]["total_debit"], json!("3085871.600"));
+        assert_eq!(actual["trailer"]["number_records"], json!(260), "Counts are not amounts");
+    }
+
+    #[test]
+    fn json_shape_signed_balances_and_rows() {
+        let shape = shape(KeyCase::Camel, AccountTagging::External, DateFormat::Iso, AmountFormat::Decimal);
+        let mut coda = parse_coda();
+        coda.old_balance.old_balance_sign = Sign::Debit;
+        coda.old_balance.old_balance = 1250;
+        coda.new_balance.new_balance_sign = Sign::Debit;
+
+        let actual = shape.to_value(&coda).unwrap();
+
+        assert_eq!(actual["oldBalance"]["oldBalance"], json!("-1.250"), "Debit balances should be negative");
+        assert_eq!(actual["newBalance"]["newBalance"], json!("-9405296.990"));
+
+        let rows = shape.to_value(&movement_rows(&[coda])).unwrap();
+        assert_eq!(rows[0]["sign"], json!("Debit"));
+        assert_eq!(rows[0]["amount"], json!("-2578.250"), "Row amounts should follow their sign");
+        assert_eq!(rows[0]["signedAmount"], json!("-2578.250"));
+        assert_eq!(rows[1]["amount"], json!("11.210"));
+    }
+
+    #[test]
+    fn json_shape_follows_the_types() {
+        let shape = shape(KeyCase::Snake, AccountTagging::Internal, DateFormat::Dmy, AmountFormat::Decimal);
+        let coda = parse_coda();
+
+        let actual = shape.to_value(&coda).unwrap();
+
+        let account = coda.movements.iter().position(|m| m.counterparty_account.is_some()).unwrap();
+        assert_eq!(
+            actual["movements"][account]["counterparty_account"]["type"].is_string(),
+            true,
+            "Counterparty accounts should be tagged as the account of the statement"
+        );
+        let stats = shape.to_value(&account_stats(&[coda], 3)).unwrap();
+        assert_eq!(stats[0]["from_date"], json!("06/12/2006"));
+        assert_eq!(stats[0]["total_debit"], json!("3085871.600"));
+        assert_eq!(stats[0]["entries"].is_number(), true, "Counts are not amounts");
+        assert_eq!(stats[0]["months"][0]["net"].is_string(), true, "Stats amounts should be decimals");
+        assert_eq!(stats[0]["months"][0]["month"], json!("2006-12"), "Months are not dates");
+    }
+
+    #[test]
+    fn json_shape_from_str() {
+        assert_eq!("camel".parse::<KeyCase>(), Ok(KeyCase::Camel));
+        assert_eq!("internal".parse::<AccountTagging>(), Ok(AccountTagging::Internal));
+        assert_eq!("dmy".parse::<DateFormat>(), Ok(DateFormat::Dmy));
+        assert_eq!("decimal".parse::<AmountFormat>(), Ok(AmountFormat::Decimal));
+        assert_eq!("kebab".parse::<KeyCase>().is_err(), true, "kebab should not be ok");
+    }
 }
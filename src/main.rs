@@ -2,50 +2,77 @@
 extern crate error_chain;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+#[macro_use]
+extern crate rusqlite;
 
 extern crate chrono;
 
 use std::process::exit;
 use std::env;
 
+mod accounting;
+mod archive;
+mod camt053;
 mod coda;
+#[cfg(test)]
+mod fixtures;
+mod csv;
+mod db;
+mod filter;
+mod html;
+mod input;
+mod iso20022;
 mod options;
 mod errors;
 mod utils;
 mod json;
+mod movements;
+mod mt940;
+mod ofx;
+mod qif;
+mod query;
+mod schema;
+mod stats;
+mod transaction_code;
+mod table;
 mod tools;
+mod validation;
+mod xml;
 
+use accounting::AccountingOptions;
 use coda::Coda;
-use options::Options;
+use options::{Command, Options};
 use errors::*;
 
 fn run() -> Result<()> {
     let options = Options::parse_options(env::args().collect())
         .map_err(|c| exit(c))
         .unwrap();
+    if options.command == Command::Schema {
+        println!("{}", json::to_json(&schema::coda_schema())?);
+        return Ok(());
+    }
     let default_encoding = String::from("utf-8");
     let encoding_label = options.encoding_label.as_ref().unwrap_or(&default_encoding);
-    let coda_list = options
-        .coda_filenames
-        .iter()
-        .map(|f: &String| -> Result<Coda> {
-            if options.debug {
-                println!("Parsing file: {}", f);
-            }
-            Coda::parse(&f, encoding_label)
-        })
-        .collect::<Vec<_>>();
-
-    let mut had_errors = false;
-    coda_list
-        .iter()
-        .by_ref()
-        .filter(|c| c.is_err())
-        .for_each(|c| {
-            println!("Error: {:?}", c);
-            had_errors = true
-        });
+    let coda_filenames = input::expand_inputs(&options.coda_filenames, options.from_json)?;
+    let parsed = input::read_inputs(
+        &coda_filenames,
+        encoding_label,
+        options.from_json,
+        options.jobs,
+        options.debug,
+    );
+
+    // All the files are read, the errors are reported per file in the order of the files
+    let mut error_count = 0;
+    for error in parsed.iter().filter_map(|c| c.as_ref().err()) {
+        let causes = error.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        println!("Error: {}", causes.join(": "));
+        error_count += 1;
+    }
+    let had_errors = error_count > 0;
 
     if !had_errors {
         // println!("{:?}", coda_list);
@@ -63,24 +90,139 @@ fn run() -> Result<()> {
         //     println!("Trailer: {:?}", coda.trailer);
         // }
 
-        let mut coda_list = coda_list
-            .into_iter()
-            .filter(|coda| coda.is_ok())
-            .map(|coda| coda.unwrap())
-            .collect::<Vec<Coda>>();
+        // A json file or an archive can hold several coda documents, the source of each one is kept for reporting
+        let mut sources: Vec<String> = Vec::new();
+        let mut coda_list: Vec<Coda> = Vec::new();
+        for codas in parsed.into_iter() {
+            for (source, coda) in codas.unwrap() {
+                sources.push(source);
+                coda_list.push(coda);
+            }
+        }
+
+        let mut had_validation_errors = false;
+        if options.validate {
+            for (filename, coda) in sources.iter().zip(coda_list.iter()) {
+                let report = validation::validate(coda);
+                tools::print_validation(filename, &report);
+                had_validation_errors |= report.has_errors();
+            }
+        }
+
+        if options.command == Command::Validate {
+            if had_validation_errors {
+                return Err("Validation ended with errors".into());
+            }
+            return Ok(());
+        }
+
+        if options.command == Command::Import {
+            let db_filename = options.db.as_ref().unwrap();
+            let files = sources.iter().map(|s| s.as_str()).zip(coda_list.iter()).collect::<Vec<_>>();
+            let summary = db::import_file(db_filename, &files).chain_err(|| "Error while importing")?;
+            tools::print_import_summary(&summary);
+            if had_validation_errors {
+                return Err("Validation ended with errors".into());
+            }
+            return Ok(());
+        }
 
         if options.sort_by_ref {
             coda_list.sort_by(|a, b| a.header.file_reference.cmp(&b.header.file_reference));
         }
 
-        if options.json {
-            for coda in coda_list {
-                tools::print_as_json(&coda).chain_err(|| "Error while printing json")?;
+        // The html report checks the trailer against all the entries of a file, whatever the filters keep
+        let file_totals = coda_list.iter().map(|c| c.entry_totals()).collect::<Vec<_>>();
+        let filtered =
+            options.category_purpose.is_some() || options.purpose.is_some() || !options.movement_filter.is_empty();
+
+        if let Some(ref code) = options.category_purpose {
+            for coda in coda_list.iter_mut() {
+                coda.movements
+                    .retain(|m| m.category_purpose.as_ref().map_or(false, |p| p.code().eq_ignore_ascii_case(code)));
             }
         }
+
+        if let Some(ref code) = options.purpose {
+            for coda in coda_list.iter_mut() {
+                coda.movements
+                    .retain(|m| m.purpose.as_ref().map_or(false, |p| p.code().eq_ignore_ascii_case(code)));
+            }
+        }
+
+        for coda in coda_list.iter_mut() {
+            options.movement_filter.apply(coda);
+        }
+
+        if let Some(ref group_by) = options.group_by {
+            tools::print_purpose_groups(&coda_list, group_by)?;
+        }
+
+        if options.r_transactions {
+            for coda in &coda_list {
+                tools::print_r_transactions(coda);
+            }
+        }
+
+        if let Some(ref text) = options.query {
+            let result = query::execute(text, &coda_list)?;
+            print!("{}", result.format(options.query_format, &options.csv_options)?);
+        } else if options.command == Command::Stats {
+            // --by keeps the former count per ISO 20022 code
+            if options.group_by.is_none() {
+                let stats_list = stats::account_stats(&coda_list, options.stats_top);
+                if options.json {
+                    tools::print_list_as_json(&stats_list, options.json_format, &options.json_shape)
+                        .chain_err(|| "Error while printing json")?;
+                } else {
+                    print!("{}", stats::to_text(&stats_list));
+                }
+            }
+        } else if let Some(version) = options.camt053 {
+            // The closing balance of a filtered statement is rebased on the kept entries, as camt.053 and MT940
+            // readers reconcile the balances with the entries
+            if filtered {
+                coda_list.iter_mut().for_each(|coda| coda.rebase_new_balance());
+            }
+            print!("{}", camt053::to_camt053(&coda_list, version));
+        } else if options.mt940 {
+            if filtered {
+                coda_list.iter_mut().for_each(|coda| coda.rebase_new_balance());
+            }
+            print!("{}", mt940::to_mt940(&coda_list));
+        } else if options.ofx {
+            print!("{}", ofx::to_ofx(&coda_list));
+        } else if options.qif {
+            print!("{}", qif::to_qif(&coda_list));
+        } else if options.html {
+            print!("{}", html::to_html(&coda_list, &file_totals));
+        } else if let Some(format) = options.accounting {
+            let accounting_options = AccountingOptions {
+                assert_balances: !filtered,
+                ..options.accounting_options.clone()
+            };
+            print!("{}", accounting::to_accounting(&coda_list, format, &accounting_options));
+        } else if let Some(table) = options.csv {
+            print!("{}", csv::to_csv(&coda_list, table, &options.csv_options));
+        } else if options.movements {
+            let rows = movements::movement_rows(&coda_list);
+            tools::print_list_as_json(&rows, options.json_format, &options.json_shape)
+                .chain_err(|| "Error while printing json")?;
+        } else if options.json {
+            tools::print_list_as_json(&coda_list, options.json_format, &options.json_shape)
+                .chain_err(|| "Error while printing json")?;
+        } else if !options.r_transactions && options.group_by.is_none() {
+            print!(
+                "{}",
+                table::to_table(&coda_list, table::terminal_width(), options.color.enabled())
+            );
+        }
+        if had_validation_errors {
+            return Err("Validation ended with errors".into());
+        }
         Ok(())
     } else {
-        Err("Parsing ended with errors".into())
+        Err(format!("Parsing ended with errors in {} of {} files", error_count, coda_filenames.len()).into())
     }
 }
 
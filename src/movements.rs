@@ -0,0 +1,193 @@
+extern crate serde;
+
+use chrono::NaiveDate;
+
+use self::serde::ser::SerializeStruct;
+use self::serde::{Serialize, Serializer};
+
+use coda::{Coda, Movement};
+use json::{JsonAmount, JsonDate, SignedAmount};
+use transaction_code::TransactionCode;
+use utils::Sign;
+
+// One movement enriched with the context of its statement, the row model of flat and tabular exports
+#[derive(PartialEq, Debug)]
+pub struct MovementRow {
+    pub file_reference: String,
+    pub creation_date: NaiveDate,
+    pub account: String,
+    pub currency: String,
+    pub statement_number: String,
+    pub sequence: String,
+    pub detail_sequence: String,
+    // The booked entry, its detail records (globalisation) share its sequence and add up to its amount
+    pub entry: bool,
+    pub bank_reference: String,
+    pub value_date: NaiveDate,
+    pub entry_date: NaiveDate,
+    pub sign: Sign,
+    pub amount: u64,
+    pub signed_amount: i64,
+    pub transaction_code: String,
+    pub transaction_type: Option<u8>,
+    pub transaction_type_description: Option<String>,
+    pub transaction_family: Option<String>,
+    pub transaction_family_description: Option<String>,
+    pub transaction: Option<String>,
+    pub transaction_category: Option<String>,
+    pub communication: String,
+    pub customer_reference: Option<String>,
+    pub counterparty_name: Option<String>,
+    pub counterparty_account: Option<String>,
+    pub counterparty_bic: Option<String>,
+}
+
+// The amount is written with its sign, so the json shape can write it as a signed decimal
+impl Serialize for MovementRow {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut fields = s.serialize_struct("MovementRow", 26)?;
+        fields.serialize_field("file_reference", &self.file_reference)?;
+        fields.serialize_field("creation_date", &JsonDate(&self.creation_date))?;
+        fields.serialize_field("account", &self.account)?;
+        fields.serialize_field("currency", &self.currency)?;
+        fields.serialize_field("statement_number", &self.statement_number)?;
+        fields.serialize_field("sequence", &self.sequence)?;
+        fields.serialize_field("detail_sequence", &self.detail_sequence)?;
+        fields.serialize_field("entry", &self.entry)?;
+        fields.serialize_field("bank_reference", &self.bank_reference)?;
+        fields.serialize_field("value_date", &JsonDate(&self.value_date))?;
+        fields.serialize_field("entry_date", &JsonDate(&self.entry_date))?;
+        fields.serialize_field("sign", &self.sign)?;
+        fields.serialize_field("amount", &SignedAmount(&self.sign, self.amount))?;
+        fields.serialize_field("signed_amount", &JsonAmount(self.signed_amount))?;
+        fields.serialize_field("transaction_code", &self.transaction_code)?;
+        fields.serialize_field("transaction_type", &self.transaction_type)?;
+        fields.serialize_field("transaction_type_description", &self.transaction_type_description)?;
+        fields.serialize_field("transaction_family", &self.transaction_family)?;
+        fields.serialize_field("transaction_family_description", &self.transaction_family_description)?;
+        fields.serialize_field("transaction", &self.transaction)?;
+        fields.serialize_field("transaction_category", &self.transaction_category)?;
+        fields.serialize_field("communication", &self.communication)?;
+        fields.serialize_field("customer_reference", &self.customer_reference)?;
+        fields.serialize_field("counterparty_name", &self.counterparty_name)?;
+        fields.serialize_field("counterparty_account", &self.counterparty_account)?;
+        fields.serialize_field("counterparty_bic", &self.counterparty_bic)?;
+        fields.end()
+    }
+}
+
+impl MovementRow {
+    pub fn new(coda: &Coda, movement: &Movement, entry: bool) -> MovementRow {
+        let transaction_code = TransactionCode::parse(&movement.transaction_code).ok();
+        MovementRow {
+            file_reference: String::from(coda.header.file_reference.trim()),
+            creation_date: coda.header.creation_date,
+            account: String::from(coda.old_balance.account.number()),
+            currency: String::from(coda.old_balance.account.currency()),
+            statement_number: coda.old_balance.old_sequence.clone(),
+            sequence: movement.sequence.clone(),
+            detail_sequence: movement.detail_sequence.clone(),
+            entry,
+            bank_reference: movement.bank_reference.clone(),
+            value_date: movement.value_date,
+            entry_date: movement.entry_date,
+            sign: movement.sign(),
+            amount: movement.unsigned_amount(),
+            signed_amount: movement.signed_amount(),
+            transaction_code: movement.transaction_code.clone(),
+            transaction_type: transaction_code.as_ref().map(|c| c.transaction_type),
+            transaction_type_description: transaction_code
+                .as_ref()
+                .map(|c| String::from(c.type_description())),
+            transaction_family: transaction_code.as_ref().map(|c| c.family.clone()),
+            transaction_family_description: transaction_code
+                .as_ref()
+                .map(|c| String::from(c.family_description())),
+            transaction: transaction_code.as_ref().map(|c| c.transaction.clone()),
+            transaction_category: transaction_code.as_ref().map(|c| c.category.clone()),
+            communication: movement.communication.clone(),
+            customer_reference: movement.customer_reference.clone(),
+            counterparty_name: movement.counterparty_name.clone(),
+            counterparty_account: movement
+                .counterparty_account
+                .as_ref()
+                .map(|a| String::from(a.number())),
+            counterparty_bic: movement.counterparty_bic.clone(),
+        }
+    }
+}
+
+// Each entry is followed by its detail records, only the rows with entry set count in totals
+pub fn movement_rows(coda_list: &[Coda]) -> Vec<MovementRow> {
+    let mut rows = Vec::new();
+    for coda in coda_list {
+        for entry in coda.entries() {
+            for (i, movement) in entry.iter().enumerate() {
+                rows.push(MovementRow::new(coda, movement, i == 0));
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod test_movements {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use fixtures::parse_coda;
+
+    #[test]
+    fn movement_rows_valid() {
+        let coda = parse_coda();
+        let count = coda.movements.len();
+
+        let rows = movement_rows(&[coda]);
+
+        assert_eq!(rows.len(), count, "There should be one row per movement");
+        let row = &rows[0];
+        assert_eq!(row.file_reference, "00099449");
+        assert_eq!(row.creation_date, NaiveDate::from_ymd(2006, 12, 6));
+        assert_eq!(row.account, "435000000080");
+        assert_eq!(row.currency, "EUR");
+        assert_eq!(row.statement_number, "001");
+        assert_eq!(row.sequence, "0001");
+        assert_eq!(row.entry, true);
+        assert_eq!(row.sign, Sign::Debit);
+        assert_eq!(row.amount, 2578250);
+        assert_eq!(row.signed_amount, -2578250);
+        assert_eq!(row.transaction_code, "00799000");
+        assert_eq!(row.transaction_family, Some(String::from("07")));
+        assert_eq!(
+            row.transaction_family_description,
+            Some(String::from("Domestic commercial paper"))
+        );
+    }
+
+    #[test]
+    fn movement_rows_counterparty() {
+        let coda = parse_coda();
+
+        let rows = movement_rows(&[coda]);
+
+        let row = rows.iter().find(|r| r.sequence == "0007" && r.detail_sequence == "0003").unwrap();
+        assert_eq!(row.counterparty_account, Some(String::from("068226750863")));
+        assert_eq!(row.counterparty_name, Some(String::from("T.P.F.  S.A.")));
+        assert_eq!(row.entry, false, "A detail record is not an entry");
+    }
+
+    #[test]
+    fn movement_rows_entries_add_up_to_trailer() {
+        let coda = parse_coda();
+        let (debit, credit) = coda.entry_totals();
+
+        let rows = movement_rows(&[coda]);
+
+        let total = rows.iter().filter(|r| r.entry).map(|r| r.signed_amount).sum::<i64>();
+        assert_eq!(total, credit as i64 - debit as i64, "Details should not be counted twice");
+        assert_eq!(rows.iter().any(|r| !r.entry), true, "The sample has globalised entries");
+    }
+}
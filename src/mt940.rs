@@ -0,0 +1,263 @@
+use chrono::NaiveDate;
+
+use coda::{Coda, Movement};
+use utils::{format_amount, Sign};
+
+// Statement line details (:86:) are at most 6 lines of 65 characters
+const LINE_LENGTH: usize = 65;
+const DETAIL_LINES: usize = 6;
+
+// SWIFT x character set: letters, digits, space and / - ? : ( ) . , ' +
+fn is_swift_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || " /-?:().,'+".contains(c)
+}
+
+fn transliterate_char(c: char) -> String {
+    let replacement = match c {
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => "A",
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Ç' => "C",
+        'ç' => "c",
+        'È' | 'É' | 'Ê' | 'Ë' => "E",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'Ñ' => "N",
+        'ñ' => "n",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' => "O",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'Ù' | 'Ú' | 'Û' | 'Ü' => "U",
+        'ù' | 'ú' | 'û' | 'ü' => "u",
+        'Ý' => "Y",
+        'ý' | 'ÿ' => "y",
+        'ß' => "ss",
+        '&' => "+",
+        '\n' | '\t' => " ",
+        c if is_swift_char(c) => return c.to_string(),
+        _ => ".",
+    };
+    String::from(replacement)
+}
+
+pub fn transliterate(text: &str) -> String {
+    text.chars().map(transliterate_char).collect()
+}
+
+// Split on line length, a line of a field may not start with ':' or '-' as it would be read as a new field or
+// the end of the message
+fn wrap(text: &str, line_length: usize, max_lines: usize) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<char>>();
+    chars
+        .chunks(line_length)
+        .take(max_lines)
+        .map(|chunk| {
+            let line = chunk.iter().collect::<String>();
+            if line.starts_with(':') || line.starts_with('-') {
+                format!(".{}", line.get(1..).unwrap())
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
+fn truncate(text: &str, length: usize) -> String {
+    text.chars().take(length).collect()
+}
+
+fn format_date(date: &NaiveDate) -> String {
+    format!("{}", date.format("%y%m%d"))
+}
+
+// Comma as decimal separator, trailing decimal zeros are dropped but the comma is mandatory
+fn format_swift_amount(amount: u64) -> String {
+    let amount = format_amount(amount as i64, ',');
+    String::from(amount.trim_end_matches('0'))
+}
+
+fn credit_debit(sign: &Sign) -> &str {
+    match *sign {
+        Sign::Credit => "C",
+        Sign::Debit => "D",
+    }
+}
+
+fn reference(value: &str) -> String {
+    let value = transliterate(value.trim()).replace(' ', "");
+    if value.is_empty() {
+        String::from("NONREF")
+    } else {
+        truncate(&value, 16)
+    }
+}
+
+fn balance(sign: &Sign, date: &NaiveDate, currency: &str, amount: u64) -> String {
+    format!(
+        "{}{}{}{}",
+        credit_debit(sign),
+        format_date(date),
+        currency,
+        format_swift_amount(amount)
+    )
+}
+
+fn statement_line(movement: &Movement) -> String {
+    let customer_reference = movement
+        .customer_reference
+        .as_ref()
+        .map_or(String::from("NONREF"), |r| reference(r));
+    format!(
+        "{}{}{}{}NMSC{}//{}",
+        format_date(&movement.value_date),
+        movement.entry_date.format("%m%d"),
+        credit_debit(&movement.sign()),
+        format_swift_amount(movement.unsigned_amount()),
+        customer_reference,
+        reference(&movement.bank_reference)
+    )
+}
+
+// Communication and counterparty of the entry, taken from its details when the entry itself has none
+fn information(entry: &[&Movement]) -> String {
+    let mut parts = Vec::new();
+    if let Some(ogm) = entry[0].structured_reference() {
+        parts.push(format!("/REMI/OGM {}", ogm));
+    } else if !entry[0].communication.trim().is_empty() {
+        parts.push(format!("/REMI/{}", entry[0].communication.trim()));
+    }
+    if let Some(movement) = Coda::entry_counterparty(entry) {
+        if let Some(ref name) = movement.counterparty_name.as_ref().filter(|n| !n.is_empty()) {
+            parts.push(format!("/NAME/{}", name));
+        }
+        if let Some(ref account) = movement.counterparty_account {
+            parts.push(format!("/ACCT/{}", account.number()));
+        }
+        if let Some(ref bic) = movement.counterparty_bic.as_ref().filter(|b| !b.is_empty()) {
+            parts.push(format!("/BIC/{}", bic));
+        }
+    }
+    transliterate(&parts.join(""))
+}
+
+fn write_statement(output: &mut Vec<String>, coda: &Coda) {
+    let header = &coda.header;
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+    let currency = old_balance.account.currency();
+    let statement_number = old_balance.old_sequence.parse::<u32>().unwrap_or(0);
+    let sequence_number = old_balance.coda_sequence.parse::<u32>().unwrap_or(1);
+
+    output.push(format!(":20:{}", reference(&header.file_reference)));
+    output.push(format!(":25:{}", transliterate(old_balance.account.number())));
+    output.push(format!(":28C:{}/{}", statement_number, sequence_number));
+    output.push(format!(
+        ":60F:{}",
+        balance(
+            &old_balance.old_balance_sign,
+            &old_balance.old_balance_date,
+            currency,
+            old_balance.old_balance
+        )
+    ));
+    for entry in coda.entries() {
+        output.push(format!(":61:{}", statement_line(entry[0])));
+        let lines = wrap(&information(&entry), LINE_LENGTH, DETAIL_LINES);
+        for (i, line) in lines.iter().enumerate() {
+            if i == 0 {
+                output.push(format!(":86:{}", line));
+            } else {
+                output.push(line.clone());
+            }
+        }
+    }
+    output.push(format!(
+        ":62F:{}",
+        balance(
+            &new_balance.new_balance_sign,
+            &new_balance.new_balance_date,
+            currency,
+            new_balance.new_balance
+        )
+    ));
+    output.push(String::from("-"));
+}
+
+// One message per file, lines end with CRLF and messages end with '-'
+pub fn to_mt940(coda_list: &[Coda]) -> String {
+    let mut output = Vec::new();
+    for coda in coda_list {
+        write_statement(&mut output, coda);
+    }
+    output.iter().map(|line| format!("{}\r\n", line)).collect()
+}
+
+#[cfg(test)]
+mod test_mt940 {
+    use super::*;
+    use fixtures::parse_coda_list;
+
+    #[test]
+    fn to_mt940_valid() {
+        let actual = to_mt940(&parse_coda_list());
+
+        let lines = actual.split("\r\n").collect::<Vec<&str>>();
+        assert_eq!(lines[0], ":20:00099449");
+        assert_eq!(lines[1], ":25:435000000080");
+        assert_eq!(lines[2], ":28C:1/1");
+        assert_eq!(lines[3], ":60F:C061206EUR0,");
+        assert_eq!(lines[4], ":61:0612061206D2578,25NMSCNONREF//EPIB00048AWIUBTK");
+        assert_eq!(
+            lines[5],
+            ":86:/REMI/BORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI ON 49"
+        );
+        assert_eq!(lines[6], "5953", "Details should be wrapped on 65 characters");
+        assert_eq!(lines.contains(&":62F:C061207EUR9405296,99"), true, "Closing balance should be present");
+        assert_eq!(actual.ends_with("-\r\n"), true, "Message should end with '-'");
+    }
+
+    #[test]
+    fn to_mt940_line_length() {
+        let actual = to_mt940(&parse_coda_list());
+
+        for line in actual.split("\r\n") {
+            assert_eq!(line.chars().count() <= LINE_LENGTH + 4, true, "Line too long [{}]", line);
+            assert_eq!(line.chars().all(is_swift_char), true, "Invalid character in [{}]", line);
+        }
+    }
+
+    #[test]
+    fn to_mt940_structured_reference() {
+        let actual = to_mt940(&parse_coda_list());
+
+        assert_eq!(actual.contains(":86:/REMI/OGM 269021157996"), true, "OGM should be in :86:");
+    }
+
+    #[test]
+    fn transliterate_valid() {
+        assert_eq!(transliterate("Société Générale & Cie"), "Societe Generale + Cie");
+        assert_eq!(transliterate("Straße_1"), "Strasse.1");
+        assert_eq!(transliterate("LINE 1\nLINE 2"), "LINE 1 LINE 2");
+    }
+
+    #[test]
+    fn wrap_valid() {
+        let text = format!("{}:{}", "A".repeat(65), "B".repeat(10));
+
+        let actual = wrap(&text, 65, 6);
+
+        assert_eq!(actual, vec!["A".repeat(65), format!(".{}", "B".repeat(10))]);
+        assert_eq!(wrap(&"A".repeat(500), 65, 6).len(), 6);
+    }
+
+    #[test]
+    fn format_swift_amount_valid() {
+        assert_eq!(format_swift_amount(2578250), "2578,25");
+        assert_eq!(format_swift_amount(0), "0,");
+        assert_eq!(format_swift_amount(1000), "1,");
+        assert_eq!(format_swift_amount(5), "0,005");
+    }
+}
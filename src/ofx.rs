@@ -0,0 +1,142 @@
+use chrono::NaiveDate;
+
+use coda::{Coda, Movement};
+use utils::{format_amount, signed_amount, Sign};
+use xml::XmlWriter;
+
+fn format_date(date: &NaiveDate) -> String {
+    format!("{}", date.format("%Y%m%d"))
+}
+
+fn write_status(writer: &mut XmlWriter) {
+    writer.start("STATUS");
+    writer.text("CODE", "0");
+    writer.text("SEVERITY", "INFO");
+    writer.end("STATUS");
+}
+
+fn write_transaction(writer: &mut XmlWriter, entry: &[&Movement]) {
+    let movement = entry[0];
+    writer.start("STMTTRN");
+    writer.text(
+        "TRNTYPE",
+        match movement.sign() {
+            Sign::Credit => "CREDIT",
+            Sign::Debit => "DEBIT",
+        },
+    );
+    writer.text("DTPOSTED", &format_date(&movement.entry_date));
+    writer.text("DTUSER", &format_date(&movement.value_date));
+    writer.text("TRNAMT", &format_amount(movement.signed_amount(), '.'));
+    writer.text("FITID", movement.bank_reference.trim());
+    if let Some(counterparty) = Coda::entry_counterparty(entry) {
+        if let Some(ref name) = counterparty.counterparty_name.as_ref().filter(|n| !n.is_empty()) {
+            // NAME is limited to 32 characters
+            writer.text("NAME", &name.chars().take(32).collect::<String>());
+        }
+    }
+    let memo = match movement.structured_reference() {
+        Some(reference) => reference,
+        None => movement.communication.replace('\n', " ").trim().to_string(),
+    };
+    if !memo.is_empty() {
+        writer.text("MEMO", &memo);
+    }
+    writer.end("STMTTRN");
+}
+
+fn write_statement(writer: &mut XmlWriter, coda: &Coda) {
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+
+    writer.start("STMTTRNRS");
+    writer.text("TRNUID", &format!("{}-{}", coda.header.file_reference.trim(), old_balance.old_sequence));
+    write_status(writer);
+    writer.start("STMTRS");
+    writer.text("CURDEF", old_balance.account.currency());
+    writer.start("BANKACCTFROM");
+    writer.text("BANKID", &coda.header.bic);
+    writer.text("ACCTID", old_balance.account.number());
+    writer.text("ACCTTYPE", "CHECKING");
+    writer.end("BANKACCTFROM");
+    writer.start("BANKTRANLIST");
+    writer.text("DTSTART", &format_date(&old_balance.old_balance_date));
+    writer.text("DTEND", &format_date(&new_balance.new_balance_date));
+    for entry in coda.entries() {
+        write_transaction(writer, &entry);
+    }
+    writer.end("BANKTRANLIST");
+    writer.start("LEDGERBAL");
+    writer.text(
+        "BALAMT",
+        &format_amount(signed_amount(&new_balance.new_balance_sign, new_balance.new_balance), '.'),
+    );
+    writer.text("DTASOF", &format_date(&new_balance.new_balance_date));
+    writer.end("LEDGERBAL");
+    writer.end("STMTRS");
+    writer.end("STMTTRNRS");
+}
+
+// OFX 2.2 document with one statement response per file
+pub fn to_ofx(coda_list: &[Coda]) -> String {
+    let mut writer = XmlWriter::new();
+    writer.instruction("xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"");
+    writer.instruction(
+        "OFX OFXHEADER=\"200\" VERSION=\"220\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"",
+    );
+    writer.start("OFX");
+    writer.start("SIGNONMSGSRSV1");
+    writer.start("SONRS");
+    write_status(&mut writer);
+    let server_date = coda_list
+        .iter()
+        .map(|coda| coda.header.creation_date)
+        .max()
+        .map_or(String::from("19700101"), |date| format_date(&date));
+    writer.text("DTSERVER", &server_date);
+    writer.text("LANGUAGE", "ENG");
+    writer.end("SONRS");
+    writer.end("SIGNONMSGSRSV1");
+    writer.start("BANKMSGSRSV1");
+    for coda in coda_list {
+        write_statement(&mut writer, coda);
+    }
+    writer.end("BANKMSGSRSV1");
+    writer.end("OFX");
+    writer.output
+}
+
+#[cfg(test)]
+mod test_ofx {
+    use super::*;
+    use fixtures::{Document, Node, child, parse_coda, path};
+
+    #[test]
+    fn to_ofx_valid() {
+        let coda = parse_coda();
+        let entries = coda.entries().len();
+
+        let ofx = to_ofx(&[coda]);
+
+        assert_eq!(ofx.contains("<?OFX OFXHEADER=\"200\" VERSION=\"220\""), true, "OFX header should be present");
+        let document = Document::parse(&ofx);
+        assert_eq!(document.is_ok(), true, "OFX should be well formed");
+        let document = document.unwrap();
+        let statement = path(document.root_element(), &["BANKMSGSRSV1", "STMTTRNRS", "STMTRS"]);
+        assert_eq!(child(statement, "CURDEF").text(), Some("EUR"));
+        assert_eq!(path(statement, &["BANKACCTFROM", "ACCTID"]).text(), Some("435000000080"));
+        assert_eq!(path(statement, &["LEDGERBAL", "BALAMT"]).text(), Some("9405296.990"));
+        assert_eq!(path(statement, &["LEDGERBAL", "DTASOF"]).text(), Some("20061207"));
+
+        let list = child(statement, "BANKTRANLIST");
+        let transactions = list.children()
+            .filter(|n| n.tag_name().name() == "STMTTRN")
+            .collect::<Vec<Node>>();
+        assert_eq!(transactions.len(), entries, "There should be one STMTTRN per entry");
+        let transaction = transactions[0];
+        assert_eq!(child(transaction, "TRNTYPE").text(), Some("DEBIT"));
+        assert_eq!(child(transaction, "TRNAMT").text(), Some("-2578.250"));
+        assert_eq!(child(transaction, "FITID").text(), Some("EPIB00048 AWIUBTKAPUO"));
+        assert_eq!(child(transaction, "DTPOSTED").text(), Some("20061206"));
+    }
+}
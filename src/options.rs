@@ -2,79 +2,779 @@ extern crate argparse;
 
 use std::io::{stderr, stdout};
 use std::result::Result;
+use std::str::FromStr;
 
-use self::argparse::{ArgumentParser, List, Print, StoreOption, StoreTrue};
+use self::argparse::{ArgumentParser, Collect, List, Print, Store, StoreConst, StoreOption, StoreTrue};
+
+use accounting::{AccountingFormat, AccountingOptions};
+use camt053::CamtVersion;
+use coda::is_encoding_label;
+use csv::{CsvOptions, CsvTable};
+use filter::MovementFilter;
+use json::{JsonFormat, JsonShape};
+use query::QueryFormat;
+use table::ColorChoice;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Command {
+    // No command given, all the flags of the flat command line are available
+    Flat,
+    Parse,
+    Validate,
+    List,
+    Convert,
+    Stats,
+    Import,
+    Schema,
+    Query,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Command, String> {
+        match s {
+            "parse" => Ok(Command::Parse),
+            "validate" => Ok(Command::Validate),
+            "list" => Ok(Command::List),
+            "convert" => Ok(Command::Convert),
+            "stats" => Ok(Command::Stats),
+            "import" => Ok(Command::Import),
+            "schema" => Ok(Command::Schema),
+            "query" => Ok(Command::Query),
+            _ => Err(format!("Invalid command [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ConvertTarget {
+    Json,
+    Movements,
+    Csv,
+    Camt053,
+    Mt940,
+    Ofx,
+    Qif,
+    Html,
+    Beancount,
+    Ledger,
+}
+
+impl FromStr for ConvertTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<ConvertTarget, String> {
+        match s {
+            "json" => Ok(ConvertTarget::Json),
+            "movements" => Ok(ConvertTarget::Movements),
+            "csv" => Ok(ConvertTarget::Csv),
+            "camt053" => Ok(ConvertTarget::Camt053),
+            "mt940" => Ok(ConvertTarget::Mt940),
+            "ofx" => Ok(ConvertTarget::Ofx),
+            "qif" => Ok(ConvertTarget::Qif),
+            "html" => Ok(ConvertTarget::Html),
+            "beancount" => Ok(ConvertTarget::Beancount),
+            "ledger" => Ok(ConvertTarget::Ledger),
+            _ => Err(format!("Invalid conversion target [{}]", s)),
+        }
+    }
+}
+
+const COMMANDS_HELP: &str = "Parse coda files. Commands: parse, validate, list, convert, stats, import, schema \
+                             and query, see coda-rs <command> --help. Without a command, the flags below are used. \
+                             The command names are reserved as first argument, write ./list to read a file named \
+                             list.";
 
 pub struct Options {
+    pub command: Command,
+    pub db: Option<String>,
     pub coda_filenames: Vec<String>,
     pub json: bool,
+    pub json_format: JsonFormat,
+    pub json_shape: JsonShape,
+    pub movements: bool,
+    pub csv: Option<CsvTable>,
+    pub csv_options: CsvOptions,
+    pub camt053: Option<CamtVersion>,
+    pub mt940: bool,
+    pub ofx: bool,
+    pub qif: bool,
+    pub html: bool,
+    pub accounting: Option<AccountingFormat>,
+    pub accounting_options: AccountingOptions,
+    pub color: ColorChoice,
     pub debug: bool,
     pub encoding_label: Option<String>,
+    pub from_json: bool,
+    // 0 uses one job per processor
+    pub jobs: usize,
     pub sort_by_ref: bool,
+    pub validate: bool,
+    pub r_transactions: bool,
+    pub category_purpose: Option<String>,
+    pub purpose: Option<String>,
+    pub group_by: Option<String>,
+    pub movement_filter: MovementFilter,
+    pub query: Option<String>,
+    pub query_format: QueryFormat,
+    pub stats_top: usize,
+    filter_args: FilterArgs,
+}
+
+// Options shared by the commands reading coda files
+fn add_input_options<'a>(
+    ap: &mut ArgumentParser<'a>,
+    coda_filenames: &'a mut Vec<String>,
+    encoding_label: &'a mut Option<String>,
+    from_json: &'a mut bool,
+    jobs: &'a mut usize,
+    debug: &'a mut bool,
+) {
+    ap.refer(debug).add_option(
+        &["-d", "--debug"],
+        StoreTrue,
+        "Debug parsed coda data on the console",
+    );
+    ap.refer(encoding_label).add_option(
+        &["-e", "--encoding"],
+        StoreOption,
+        "Encoding for reading, use a whatwg label - See https://encoding.spec.whatwg.org/#concept-encoding-get (default to utf-8)",
+    );
+    ap.refer(from_json).add_option(
+        &["--from-json"],
+        StoreTrue,
+        "Read json written by --json (pretty, array or ndjson) instead of coda files",
+    );
+    ap.refer(jobs).add_option(
+        &["--jobs"],
+        Store,
+        "Number of files parsed at the same time (default to the number of processors)",
+    );
+    ap.refer(coda_filenames)
+        .add_argument(
+            "coda_files",
+            List,
+            "Coda files, directories (searched recursively), glob patterns or @file listing one per line",
+        )
+        .required();
+}
+
+// Movement filter flags, turned into a MovementFilter once parsed
+#[derive(Default)]
+struct FilterArgs {
+    expressions: Vec<String>,
+    from: Option<String>,
+    to: Option<String>,
+    min_amount: Option<String>,
+    counterparty: Option<String>,
+    grep: Option<String>,
+}
+
+impl FilterArgs {
+    fn to_filter(&self) -> ::errors::Result<MovementFilter> {
+        let mut filter = MovementFilter::default();
+        for expression in &self.expressions {
+            filter.add_expression(expression)?;
+        }
+        let criteria = [
+            ("from", &self.from),
+            ("to", &self.to),
+            ("min-amount", &self.min_amount),
+            ("counterparty", &self.counterparty),
+            ("grep", &self.grep),
+        ];
+        for &(key, value) in criteria.iter() {
+            if let Some(ref value) = *value {
+                filter.add_criterion(key, value)?;
+            }
+        }
+        Ok(filter)
+    }
+}
+
+fn add_selection_options<'a>(
+    ap: &mut ArgumentParser<'a>,
+    sort_by_ref: &'a mut bool,
+    category_purpose: &'a mut Option<String>,
+    purpose: &'a mut Option<String>,
+    filter_args: &'a mut FilterArgs,
+) {
+    ap.refer(sort_by_ref).add_option(
+        &["--sort-ref"],
+        StoreTrue,
+        "Sort by file reference",
+    );
+    ap.refer(category_purpose).add_option(
+        &["--category-purpose"],
+        StoreOption,
+        "Only keep movements with this ISO 20022 category purpose code (ie SALA, SUPP)",
+    );
+    ap.refer(purpose).add_option(
+        &["--purpose"],
+        StoreOption,
+        "Only keep movements with this ISO 20022 purpose code (ie ELEC, GDDS)",
+    );
+    ap.refer(&mut filter_args.expressions).add_option(
+        &["--filter"],
+        Collect,
+        "Only keep entries matching key=value criteria separated by commas: from, to (yyyy-mm-dd value dates), \
+         min-amount, max-amount, sign (debit or credit), counterparty, family, ogm or grep; a value runs up to the next key=",
+    );
+    ap.refer(&mut filter_args.from).add_option(
+        &["--from"],
+        StoreOption,
+        "Only keep entries with a value date on or after this date (yyyy-mm-dd)",
+    );
+    // --to is the target format of the convert command, the end date is --until everywhere
+    ap.refer(&mut filter_args.to).add_option(
+        &["--until"],
+        StoreOption,
+        "Only keep entries with a value date on or before this date (yyyy-mm-dd)",
+    );
+    ap.refer(&mut filter_args.min_amount).add_option(
+        &["--min-amount"],
+        StoreOption,
+        "Only keep entries with at least this amount, debit or credit (ie 1000.50)",
+    );
+    ap.refer(&mut filter_args.counterparty).add_option(
+        &["--counterparty"],
+        StoreOption,
+        "Only keep entries whose counterparty name or account number contains this text",
+    );
+    ap.refer(&mut filter_args.grep).add_option(
+        &["--grep"],
+        StoreOption,
+        "Only keep entries whose communication contains this text",
+    );
+}
+
+fn add_json_options<'a>(ap: &mut ArgumentParser<'a>, json_format: &'a mut JsonFormat, json_shape: &'a mut JsonShape) {
+    ap.refer(json_format).add_option(
+        &["--json-format"],
+        Store,
+        "Json layout for multiple files: pretty (default), array or ndjson",
+    );
+    ap.refer(&mut json_shape.key_case).add_option(
+        &["--json-keys"],
+        Store,
+        "Json key naming: snake (default) or camel",
+    );
+    ap.refer(&mut json_shape.account_tagging).add_option(
+        &["--json-account"],
+        Store,
+        "Json account variants: external (default, {\"IBANBelgianAccountNumber\": {...}}) or internal ({\"type\": \"iban_belgian\", ...})",
+    );
+    ap.refer(&mut json_shape.date_format).add_option(
+        &["--json-dates"],
+        Store,
+        "Json dates: iso (default, yyyy-mm-dd) or dmy (dd/mm/yyyy)",
+    );
+    ap.refer(&mut json_shape.amount_format).add_option(
+        &["--json-amounts"],
+        Store,
+        "Json amounts: integer (default, thousandths as in the coda file) or decimal (decimal strings, negative for debits and debit balances)",
+    );
+}
+
+fn add_csv_options<'a>(ap: &mut ArgumentParser<'a>, csv_options: &'a mut CsvOptions, csv_no_header: &'a mut bool) {
+    ap.refer(&mut csv_options.delimiter).add_option(
+        &["--csv-delimiter"],
+        Store,
+        "Csv field delimiter (default to ',', use ';' for Belgian Excel)",
+    );
+    ap.refer(&mut csv_options.decimal_separator).add_option(
+        &["--csv-decimal-separator"],
+        Store,
+        "Csv decimal separator for amounts (default to '.')",
+    );
+    ap.refer(&mut csv_options.date_format).add_option(
+        &["--csv-date-format"],
+        Store,
+        "Csv date format, see chrono strftime (default to %Y-%m-%d)",
+    );
+    ap.refer(csv_no_header).add_option(
+        &["--csv-no-header"],
+        StoreTrue,
+        "Do not write the csv header row",
+    );
+    ap.refer(&mut csv_options.quoting).add_option(
+        &["--csv-quote"],
+        Store,
+        "Csv quoting: minimal (default) or all",
+    );
+}
+
+fn add_accounting_options<'a>(ap: &mut ArgumentParser<'a>, accounting_options: &'a mut AccountingOptions) {
+    ap.refer(&mut accounting_options.asset_account).add_option(
+        &["--asset-account"],
+        StoreOption,
+        "Beancount / Ledger account of the coda account (default to Assets:Bank:<account number>)",
+    );
+    ap.refer(&mut accounting_options.expense_account).add_option(
+        &["--expense-account"],
+        Store,
+        "Beancount / Ledger account the debits are booked against (default to Expenses:Unknown)",
+    );
+    ap.refer(&mut accounting_options.income_account).add_option(
+        &["--income-account"],
+        Store,
+        "Beancount / Ledger account the credits are booked against (default to Income:Unknown)",
+    );
+    ap.refer(&mut accounting_options.opening_account).add_option(
+        &["--opening-account"],
+        Store,
+        "Beancount / Ledger account the opening balance of each asset account comes from (default to \
+         Equity:Opening-Balances)",
+    );
+}
+
+// Codes the movements can be counted by
+const GROUP_BY_VALUES: [&str; 2] = ["category-purpose", "purpose"];
+
+fn add_group_by_option<'a>(ap: &mut ArgumentParser<'a>, names: &[&'a str], group_by: &'a mut Option<String>) {
+    ap.refer(group_by).add_option(
+        names,
+        StoreOption,
+        "Count movements per code, one of category-purpose or purpose",
+    );
 }
 
 impl Options {
-    pub fn parse_options(args: Vec<String>) -> Result<Options, i32> {
-        let mut options = Options {
+    fn new() -> Options {
+        Options {
+            command: Command::Flat,
+            db: None,
             coda_filenames: vec![],
             json: false,
+            json_format: JsonFormat::Pretty,
+            json_shape: JsonShape::default(),
+            movements: false,
+            csv: None,
+            csv_options: CsvOptions::default(),
+            camt053: None,
+            mt940: false,
+            ofx: false,
+            qif: false,
+            html: false,
+            accounting: None,
+            accounting_options: AccountingOptions::default(),
+            color: ColorChoice::Auto,
             debug: false,
             encoding_label: None,
+            from_json: false,
+            jobs: 0,
             sort_by_ref: false,
-        };
+            validate: false,
+            r_transactions: false,
+            category_purpose: None,
+            purpose: None,
+            group_by: None,
+            movement_filter: MovementFilter::default(),
+            query: None,
+            query_format: QueryFormat::Table,
+            stats_top: 10,
+            filter_args: FilterArgs::default(),
+        }
+    }
+
+    // A known command as first argument selects its own parser, the flat command line is kept otherwise
+    pub fn parse_options(mut args: Vec<String>) -> Result<Options, i32> {
+        let mut options = Options::new();
+        if let Some(command) = args.get(1).and_then(|a| a.parse::<Command>().ok()) {
+            options.command = command;
+            let name = args.remove(1);
+            args[0] = format!("{} {}", args[0], name);
+        }
+        let mut csv_no_header = false;
+        match options.command {
+            Command::Flat => options.parse_flat(args, &mut csv_no_header)?,
+            Command::Parse => options.parse_parse(args)?,
+            Command::Validate => options.parse_validate(args)?,
+            Command::List => options.parse_list(args, &mut csv_no_header)?,
+            Command::Convert => options.parse_convert(args, &mut csv_no_header)?,
+            Command::Stats => options.parse_stats(args)?,
+            Command::Import => options.parse_import(args)?,
+            Command::Schema => options.parse_schema(args)?,
+            Command::Query => options.parse_query(args, &mut csv_no_header)?,
+        }
+        options.csv_options.header = !csv_no_header;
+        if let Some(ref label) = options.encoding_label {
+            if !is_encoding_label(label) {
+                eprintln!("Unknown encoding [{}], use a whatwg label ie utf-8 or latin1", label);
+                return Err(2);
+            }
+        }
+        if let Err(e) = options.csv_options.validate() {
+            eprintln!("{}", e);
+            return Err(2);
+        }
+        if let Some(ref group_by) = options.group_by {
+            if !GROUP_BY_VALUES.contains(&group_by.as_str()) {
+                eprintln!("Invalid group by value [{}], use category-purpose or purpose", group_by);
+                return Err(2);
+            }
+        }
+        match options.filter_args.to_filter() {
+            Ok(filter) => options.movement_filter = filter,
+            Err(e) => {
+                eprintln!("{}", e);
+                return Err(2);
+            }
+        }
+        Ok(options)
+    }
+
+    fn parse_flat(&mut self, args: Vec<String>, csv_no_header: &mut bool) -> Result<(), i32> {
+        let mut ap = ArgumentParser::new();
+        ap.set_description(COMMANDS_HELP);
+        ap.refer(&mut self.json).add_option(
+            &["-j", "--json"],
+            StoreTrue,
+            "Convert coda files to json",
+        );
+        add_json_options(&mut ap, &mut self.json_format, &mut self.json_shape);
+        ap.refer(&mut self.movements).add_option(
+            &["--movements"],
+            StoreTrue,
+            "Export one flat json row per movement with its statement context",
+        );
+        ap.refer(&mut self.csv).add_option(
+            &["--csv"],
+            StoreOption,
+            "Export a csv table: movements, statements or information",
+        );
+        add_csv_options(&mut ap, &mut self.csv_options, csv_no_header);
+        ap.refer(&mut self.camt053).add_option(
+            &["--camt053"],
+            StoreOption,
+            "Export an ISO 20022 camt.053 statement message: 001.02 or 001.08",
+        );
+        ap.refer(&mut self.mt940).add_option(
+            &["--mt940"],
+            StoreTrue,
+            "Export a SWIFT MT940 customer statement message per file",
+        );
+        ap.refer(&mut self.ofx).add_option(
+            &["--ofx"],
+            StoreTrue,
+            "Export an OFX 2 bank statement document",
+        );
+        ap.refer(&mut self.qif).add_option(
+            &["--qif"],
+            StoreTrue,
+            "Export QIF bank transactions",
+        );
+        ap.refer(&mut self.html).add_option(
+            &["--html"],
+            StoreTrue,
+            "Export a printable html report of the statements",
+        );
+        ap.refer(&mut self.accounting)
+            .add_option(
+                &["--beancount"],
+                StoreConst(Some(AccountingFormat::Beancount)),
+                "Export Beancount transactions and balance assertions",
+            )
+            .add_option(
+                &["--ledger"],
+                StoreConst(Some(AccountingFormat::Ledger)),
+                "Export Ledger transactions and balance assertions",
+            );
+        add_accounting_options(&mut ap, &mut self.accounting_options);
+        ap.refer(&mut self.color).add_option(
+            &["--color"],
+            Store,
+            "Colors of the statement table shown when no export is chosen: auto (default), always or never",
+        );
+        ap.refer(&mut self.validate).add_option(
+            &["--validate"],
+            StoreTrue,
+            "Validate account numbers and report findings on stderr",
+        );
+        ap.refer(&mut self.r_transactions).add_option(
+            &["--r-transactions"],
+            StoreTrue,
+            "List rejected, returned, refunded, reversed and cancelled movements with their reason on stderr",
+        );
+        add_group_by_option(&mut ap, &["--group-by"], &mut self.group_by);
+        add_selection_options(
+            &mut ap,
+            &mut self.sort_by_ref,
+            &mut self.category_purpose,
+            &mut self.purpose,
+            &mut self.filter_args,
+        );
+        add_input_options(
+            &mut ap,
+            &mut self.coda_filenames,
+            &mut self.encoding_label,
+            &mut self.from_json,
+            &mut self.jobs,
+            &mut self.debug,
+        );
+        ap.add_option(
+            &["-v", "--version"],
+            Print(
+                format!(
+                    "{} {} ({} {})",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION"),
+                    env!("GIT_COMMIT"),
+                    env!("BUILD_DATE")
+                ).to_string(),
+            ),
+            "Show version",
+        );
+        ap.parse(args, &mut stdout(), &mut stderr())
+    }
+
+    fn parse_parse(&mut self, args: Vec<String>) -> Result<(), i32> {
+        self.json = true;
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Parse coda files and write them as json");
+        add_json_options(&mut ap, &mut self.json_format, &mut self.json_shape);
+        ap.refer(&mut self.movements).add_option(
+            &["--movements"],
+            StoreTrue,
+            "Write one flat row per movement with its statement context",
+        );
+        add_selection_options(
+            &mut ap,
+            &mut self.sort_by_ref,
+            &mut self.category_purpose,
+            &mut self.purpose,
+            &mut self.filter_args,
+        );
+        add_input_options(
+            &mut ap,
+            &mut self.coda_filenames,
+            &mut self.encoding_label,
+            &mut self.from_json,
+            &mut self.jobs,
+            &mut self.debug,
+        );
+        ap.parse(args, &mut stdout(), &mut stderr())
+    }
+
+    fn parse_validate(&mut self, args: Vec<String>) -> Result<(), i32> {
+        self.validate = true;
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Validate coda files, findings are reported on stderr and errors end with exit code 1");
+        add_input_options(
+            &mut ap,
+            &mut self.coda_filenames,
+            &mut self.encoding_label,
+            &mut self.from_json,
+            &mut self.jobs,
+            &mut self.debug,
+        );
+        ap.parse(args, &mut stdout(), &mut stderr())
+    }
+
+    fn parse_list(&mut self, args: Vec<String>, csv_no_header: &mut bool) -> Result<(), i32> {
+        let mut table = CsvTable::Movements;
         {
             let mut ap = ArgumentParser::new();
-            ap.set_description("Parse coda files");
-            ap.refer(&mut options.json).add_option(
-                &["-j", "--json"],
-                StoreTrue,
-                "Convert coda files to json",
+            ap.set_description("List movements, statements or information records as csv");
+            ap.refer(&mut table)
+                .add_argument("table", Store, "Table to list: movements, statements or information")
+                .required();
+            add_csv_options(&mut ap, &mut self.csv_options, csv_no_header);
+            add_selection_options(
+                &mut ap,
+                &mut self.sort_by_ref,
+                &mut self.category_purpose,
+                &mut self.purpose,
+                &mut self.filter_args,
             );
-            ap.refer(&mut options.debug).add_option(
-                &["-d", "--debug"],
-                StoreTrue,
-                "Debug parsed coda data on the console",
+            add_input_options(
+                &mut ap,
+                &mut self.coda_filenames,
+                &mut self.encoding_label,
+                &mut self.from_json,
+                &mut self.jobs,
+                &mut self.debug,
             );
-            ap.refer(&mut options.sort_by_ref).add_option(
-                &["--sort-ref"],
+            ap.parse(args, &mut stdout(), &mut stderr())?;
+        }
+        self.csv = Some(table);
+        Ok(())
+    }
+
+    fn parse_convert(&mut self, args: Vec<String>, csv_no_header: &mut bool) -> Result<(), i32> {
+        let mut target = ConvertTarget::Json;
+        let mut camt_version = CamtVersion::V02;
+        let mut table = CsvTable::Movements;
+        {
+            let mut ap = ArgumentParser::new();
+            ap.set_description("Convert coda files to another format");
+            ap.refer(&mut target)
+                .add_option(
+                    &["--to"],
+                    Store,
+                    "Target format: json, movements, csv, camt053, mt940, ofx, qif, html, beancount or ledger",
+                )
+                .required();
+            ap.refer(&mut camt_version).add_option(
+                &["--camt-version"],
+                Store,
+                "camt.053 version: 001.02 (default) or 001.08",
+            );
+            ap.refer(&mut table).add_option(
+                &["--table"],
+                Store,
+                "Csv table: movements (default), statements or information",
+            );
+            add_csv_options(&mut ap, &mut self.csv_options, csv_no_header);
+            add_json_options(&mut ap, &mut self.json_format, &mut self.json_shape);
+            add_accounting_options(&mut ap, &mut self.accounting_options);
+            add_selection_options(
+                &mut ap,
+                &mut self.sort_by_ref,
+                &mut self.category_purpose,
+                &mut self.purpose,
+                &mut self.filter_args,
+            );
+            add_input_options(
+                &mut ap,
+                &mut self.coda_filenames,
+                &mut self.encoding_label,
+                &mut self.from_json,
+                &mut self.jobs,
+                &mut self.debug,
+            );
+            ap.parse(args, &mut stdout(), &mut stderr())?;
+        }
+        match target {
+            ConvertTarget::Json => self.json = true,
+            ConvertTarget::Movements => self.movements = true,
+            ConvertTarget::Csv => self.csv = Some(table),
+            ConvertTarget::Camt053 => self.camt053 = Some(camt_version),
+            ConvertTarget::Mt940 => self.mt940 = true,
+            ConvertTarget::Ofx => self.ofx = true,
+            ConvertTarget::Qif => self.qif = true,
+            ConvertTarget::Html => self.html = true,
+            ConvertTarget::Beancount => self.accounting = Some(AccountingFormat::Beancount),
+            ConvertTarget::Ledger => self.accounting = Some(AccountingFormat::Ledger),
+        }
+        Ok(())
+    }
+
+    fn parse_stats(&mut self, args: Vec<String>) -> Result<(), i32> {
+        {
+            let mut ap = ArgumentParser::new();
+            ap.set_description(
+                "Summary per account: statements, date range, balances, totals, transaction families, \
+                 top counterparties and monthly cash-flow",
+            );
+            ap.refer(&mut self.stats_top).add_option(
+                &["--top"],
+                Store,
+                "Number of counterparties listed, the ones with the largest amounts (default to 10)",
+            );
+            ap.refer(&mut self.json).add_option(
+                &["-j", "--json"],
                 StoreTrue,
-                "Sort by file reference",
+                "Write the summaries as json",
             );
-            ap.refer(&mut options.encoding_label).add_option(
-                &["-e", "--encoding"],
-                StoreOption,
-                "Encoding for reading, use a whatwg label - See https://encoding.spec.whatwg.org/#concept-encoding-get (default to utf-8)",
+            add_json_options(&mut ap, &mut self.json_format, &mut self.json_shape);
+            add_group_by_option(&mut ap, &["--by"], &mut self.group_by);
+            add_selection_options(
+                &mut ap,
+                &mut self.sort_by_ref,
+                &mut self.category_purpose,
+                &mut self.purpose,
+                &mut self.filter_args,
             );
-            ap.refer(&mut options.coda_filenames)
-                .add_argument("coda_files", List, "List of Coda files to parse")
-                .required();
-            ap.add_option(
-                &["-v", "--version"],
-                Print(
-                    format!(
-                        "{} {} ({} {})",
-                        env!("CARGO_PKG_NAME"),
-                        env!("CARGO_PKG_VERSION"),
-                        env!("GIT_COMMIT"),
-                        env!("BUILD_DATE")
-                    ).to_string(),
-                ),
-                "Show version",
+            add_input_options(
+                &mut ap,
+                &mut self.coda_filenames,
+                &mut self.encoding_label,
+                &mut self.from_json,
+                &mut self.jobs,
+                &mut self.debug,
             );
-            match ap.parse(args, &mut stdout(), &mut stderr()) {
-                Ok(_) => {}
-                Err(err) => {
-                    return Err(err);
-                }
-            }
+            ap.parse(args, &mut stdout(), &mut stderr())
         }
-        Ok(options)
+    }
+
+    fn parse_import(&mut self, args: Vec<String>) -> Result<(), i32> {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Import coda files into a sqlite database, files already imported are skipped");
+        ap.refer(&mut self.db)
+            .add_option(&["--db"], StoreOption, "Sqlite database to import into")
+            .required();
+        ap.refer(&mut self.validate).add_option(
+            &["--validate"],
+            StoreTrue,
+            "Validate account numbers and report findings on stderr",
+        );
+        add_input_options(
+            &mut ap,
+            &mut self.coda_filenames,
+            &mut self.encoding_label,
+            &mut self.from_json,
+            &mut self.jobs,
+            &mut self.debug,
+        );
+        ap.parse(args, &mut stdout(), &mut stderr())
+    }
+
+    fn parse_schema(&mut self, args: Vec<String>) -> Result<(), i32> {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Print the json schema of the documents written by --json");
+        ap.parse(args, &mut stdout(), &mut stderr())
+    }
+
+    fn parse_query(&mut self, args: Vec<String>, csv_no_header: &mut bool) -> Result<(), i32> {
+        let mut ap = ArgumentParser::new();
+        ap.set_description(
+            "Query movements (booked entries), details (detail records of globalised entries), information or \
+             statements, ie: movements where amount > 1000 and counterparty_name ~ \"ACME\" group by \
+             transaction_code sum amount",
+        );
+        ap.refer(&mut self.query)
+            .add_argument("query", StoreOption, "Query to run")
+            .required();
+        ap.refer(&mut self.query_format).add_option(
+            &["--format"],
+            Store,
+            "Output format: table (default), csv or json",
+        );
+        add_csv_options(&mut ap, &mut self.csv_options, csv_no_header);
+        add_selection_options(
+            &mut ap,
+            &mut self.sort_by_ref,
+            &mut self.category_purpose,
+            &mut self.purpose,
+            &mut self.filter_args,
+        );
+        add_input_options(
+            &mut ap,
+            &mut self.coda_filenames,
+            &mut self.encoding_label,
+            &mut self.from_json,
+            &mut self.jobs,
+            &mut self.debug,
+        );
+        ap.parse(args, &mut stdout(), &mut stderr())
     }
 }
 
 #[cfg(test)]
 mod test_options {
-    use super::Options;
+    use chrono::NaiveDate;
+
+    use super::{Command, Options};
+    use accounting::{AccountingFormat, AccountingOptions};
+    use camt053::CamtVersion;
+    use csv::{CsvOptions, CsvQuoting, CsvTable};
+    use filter::MovementFilter;
+    use json::{AccountTagging, AmountFormat, DateFormat, JsonFormat, JsonShape, KeyCase};
+    use query::QueryFormat;
+    use table::ColorChoice;
+    use utils::Sign;
 
     #[test]
     fn parse_verbose() {
@@ -84,14 +784,351 @@ mod test_options {
         assert_eq!(options.err(), Some(0));
     }
 
+    #[test]
+    fn parse_import() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("import"),
+            String::from("--db"),
+            String::from("statements.sqlite"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::Import);
+        assert_eq!(options.db, Some(String::from("statements.sqlite")));
+        assert_eq!(options.coda_filenames, vec!["coda_file1.txt"]);
+    }
+
+    #[test]
+    fn parse_import_without_db() {
+        let args = vec![String::from("coda-rs"), String::from("import"), String::from("coda_file1.txt")];
+        let options = Options::parse_options(args);
+        assert_eq!(options.err(), Some(2));
+    }
+
+    #[test]
+    fn parse_schema() {
+        let args = vec![String::from("coda-rs"), String::from("schema")];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::Schema);
+        assert_eq!(options.coda_filenames.len(), 0);
+    }
+
+    #[test]
+    fn parse_query_command() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("query"),
+            String::from("movements where amount > 1000 count"),
+            String::from("--format"),
+            String::from("json"),
+            String::from("coda_file1.txt"),
+            String::from("coda_file2.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::Query);
+        assert_eq!(options.query, Some(String::from("movements where amount > 1000 count")));
+        assert_eq!(options.query_format, QueryFormat::Json);
+        assert_eq!(options.coda_filenames, vec!["coda_file1.txt", "coda_file2.txt"]);
+    }
+
+    #[test]
+    fn parse_validate_command() {
+        let args = vec![String::from("coda-rs"), String::from("validate"), String::from("coda_file1.txt")];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::Validate);
+        assert_eq!(options.validate, true);
+        assert_eq!(options.coda_filenames, vec!["coda_file1.txt"]);
+    }
+
+    #[test]
+    fn parse_list_command() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("list"),
+            String::from("information"),
+            String::from("--csv-delimiter"),
+            String::from(";"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::List);
+        assert_eq!(options.csv, Some(CsvTable::Information));
+        assert_eq!(options.csv_options.delimiter, ';');
+        assert_eq!(options.coda_filenames, vec!["coda_file1.txt"]);
+    }
+
+    #[test]
+    fn parse_convert_command() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("convert"),
+            String::from("--to"),
+            String::from("camt053"),
+            String::from("--camt-version"),
+            String::from("001.08"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::Convert);
+        assert_eq!(options.camt053, Some(CamtVersion::V08));
+        assert_eq!(options.json, false);
+    }
+
+    #[test]
+    fn parse_convert_invalid_target() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("convert"),
+            String::from("--to"),
+            String::from("pdf"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.err(), Some(2));
+    }
+
+    #[test]
+    fn parse_stats_command() {
+        let args = vec![String::from("coda-rs"), String::from("stats"), String::from("coda_file1.txt")];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.command, Command::Stats);
+        assert_eq!(options.group_by, None);
+        assert_eq!(options.stats_top, 10);
+    }
+
+    #[test]
+    fn parse_stats_command_options() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("stats"),
+            String::from("--top"),
+            String::from("5"),
+            String::from("--json"),
+            String::from("--by"),
+            String::from("purpose"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.is_ok(), true, "Returned options should be Ok");
+        let options = options.unwrap();
+        assert_eq!(options.stats_top, 5);
+        assert_eq!(options.json, true);
+        assert_eq!(options.group_by, Some(String::from("purpose")));
+    }
+
+    #[test]
+    fn parse_invalid_csv_options() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("--csv"),
+            String::from("movements"),
+            String::from("--csv-date-format"),
+            String::from("%Q"),
+            String::from("coda_file1.txt"),
+        ];
+        assert_eq!(Options::parse_options(args).err(), Some(2), "Invalid date format");
+
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("query"),
+            String::from("--format"),
+            String::from("csv"),
+            String::from("--csv-delimiter"),
+            String::from("\""),
+            String::from("movements select amount"),
+            String::from("coda_file1.txt"),
+        ];
+        assert_eq!(Options::parse_options(args).err(), Some(2), "Invalid delimiter");
+    }
+
+    #[test]
+    fn parse_invalid_encoding() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("-e"),
+            String::from("bogus"),
+            String::from("coda_file1.txt"),
+        ];
+        assert_eq!(Options::parse_options(args).err(), Some(2));
+
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("validate"),
+            String::from("-e"),
+            String::from("latin1"),
+            String::from("coda_file1.txt"),
+        ];
+        assert_eq!(Options::parse_options(args).is_ok(), true, "latin1 is a whatwg label");
+    }
+
+    #[test]
+    fn parse_invalid_group_by() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("--group-by"),
+            String::from("colour"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.err(), Some(2));
+    }
+
+    #[test]
+    fn parse_invalid_filter() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("--min-amount"),
+            String::from("a lot"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.err(), Some(2));
+    }
+
+    #[test]
+    fn parse_until_on_every_command() {
+        for command in &["list", "stats", "query"] {
+            let mut args = vec![String::from("coda-rs"), String::from(*command)];
+            match *command {
+                "list" => args.push(String::from("movements")),
+                "query" => args.push(String::from("movements count")),
+                _ => (),
+            }
+            args.extend(vec![String::from("--until"), String::from("2018-12-31"), String::from("coda_file1.txt")]);
+            let options = Options::parse_options(args).unwrap();
+            assert_eq!(options.movement_filter.to, Some(NaiveDate::from_ymd(2018, 12, 31)), "{}", command);
+        }
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("stats"),
+            String::from("--to"),
+            String::from("2018-12-31"),
+            String::from("coda_file1.txt"),
+        ];
+        assert_eq!(Options::parse_options(args).err(), Some(2), "--to is only the target format of convert");
+    }
+
+    #[test]
+    fn parse_file_named_as_command() {
+        let args = vec![String::from("coda-rs"), String::from("./list"), String::from("other.CD2")];
+        let options = Options::parse_options(args).unwrap();
+        assert_eq!(options.command, Command::Flat);
+        assert_eq!(options.coda_filenames, vec!["./list", "other.CD2"]);
+    }
+
+    #[test]
+    fn parse_convert_until() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("convert"),
+            String::from("--to"),
+            String::from("qif"),
+            String::from("--until"),
+            String::from("2018-12-31"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args).unwrap();
+        assert_eq!(options.qif, true);
+        assert_eq!(options.movement_filter.to, Some(NaiveDate::from_ymd(2018, 12, 31)));
+    }
+
+    #[test]
+    fn parse_command_flags_only_for_command() {
+        let args = vec![
+            String::from("coda-rs"),
+            String::from("validate"),
+            String::from("--mt940"),
+            String::from("coda_file1.txt"),
+        ];
+        let options = Options::parse_options(args);
+        assert_eq!(options.err(), Some(2));
+    }
+
     #[test]
     fn parse_valid_params_all_params() {
         let args = vec![
             String::from("coda-rs"),
             String::from("-j"),
+            String::from("--json-format"),
+            String::from("ndjson"),
+            String::from("--json-keys"),
+            String::from("camel"),
+            String::from("--json-account"),
+            String::from("internal"),
+            String::from("--json-dates"),
+            String::from("dmy"),
+            String::from("--json-amounts"),
+            String::from("decimal"),
+            String::from("--movements"),
+            String::from("--csv"),
+            String::from("statements"),
+            String::from("--csv-delimiter"),
+            String::from(";"),
+            String::from("--csv-decimal-separator"),
+            String::from(","),
+            String::from("--csv-date-format"),
+            String::from("%d/%m/%Y"),
+            String::from("--csv-no-header"),
+            String::from("--csv-quote"),
+            String::from("all"),
+            String::from("--camt053"),
+            String::from("001.08"),
+            String::from("--mt940"),
+            String::from("--ofx"),
+            String::from("--qif"),
+            String::from("--html"),
+            String::from("--ledger"),
+            String::from("--asset-account"),
+            String::from("Assets:KBC"),
+            String::from("--expense-account"),
+            String::from("Expenses:Bank"),
+            String::from("--income-account"),
+            String::from("Income:Sales"),
+            String::from("--opening-account"),
+            String::from("Equity:Opening"),
+            String::from("--color"),
+            String::from("never"),
             String::from("--sort-ref"),
+            String::from("--validate"),
+            String::from("--r-transactions"),
+            String::from("--category-purpose"),
+            String::from("SALA"),
+            String::from("--purpose"),
+            String::from("PAYR"),
+            String::from("--group-by"),
+            String::from("purpose"),
+            String::from("--filter"),
+            String::from("sign=debit,family=01"),
+            String::from("--from"),
+            String::from("2018-01-01"),
+            String::from("--until"),
+            String::from("2018-12-31"),
+            String::from("--min-amount"),
+            String::from("1000.50"),
+            String::from("--counterparty"),
+            String::from("ACME"),
+            String::from("--grep"),
+            String::from("INVOICE"),
             String::from("-e"),
             String::from("windows-1252"),
+            String::from("--from-json"),
+            String::from("--jobs"),
+            String::from("4"),
             String::from("coda_file1.txt"),
             String::from("coda_file2.txt"),
             String::from("coda_file3.txt"),
@@ -104,7 +1141,68 @@ mod test_options {
             vec!["coda_file1.txt", "coda_file2.txt", "coda_file3.txt"]
         );
         assert_eq!(options.json, true);
+        assert_eq!(options.json_format, JsonFormat::Ndjson);
+        assert_eq!(
+            options.json_shape,
+            JsonShape {
+                key_case: KeyCase::Camel,
+                account_tagging: AccountTagging::Internal,
+                date_format: DateFormat::Dmy,
+                amount_format: AmountFormat::Decimal,
+            }
+        );
+        assert_eq!(options.movements, true);
+        assert_eq!(options.csv, Some(CsvTable::Statements));
+        assert_eq!(
+            options.csv_options,
+            CsvOptions {
+                delimiter: ';',
+                decimal_separator: ',',
+                date_format: String::from("%d/%m/%Y"),
+                header: false,
+                quoting: CsvQuoting::All,
+            }
+        );
+        assert_eq!(options.camt053, Some(CamtVersion::V08));
+        assert_eq!(options.mt940, true);
+        assert_eq!(options.ofx, true);
+        assert_eq!(options.qif, true);
+        assert_eq!(options.html, true);
+        assert_eq!(options.accounting, Some(AccountingFormat::Ledger));
+        assert_eq!(
+            options.accounting_options,
+            AccountingOptions {
+                asset_account: Some(String::from("Assets:KBC")),
+                expense_account: String::from("Expenses:Bank"),
+                income_account: String::from("Income:Sales"),
+                opening_account: String::from("Equity:Opening"),
+                assert_balances: true,
+            }
+        );
+        assert_eq!(options.command, Command::Flat);
+        assert_eq!(options.color, ColorChoice::Never);
         assert_eq!(options.sort_by_ref, true);
+        assert_eq!(options.validate, true);
+        assert_eq!(options.r_transactions, true);
+        assert_eq!(options.category_purpose, Some(String::from("SALA")));
+        assert_eq!(options.purpose, Some(String::from("PAYR")));
+        assert_eq!(options.group_by, Some(String::from("purpose")));
+        assert_eq!(
+            options.movement_filter,
+            MovementFilter {
+                from: Some(NaiveDate::from_ymd(2018, 1, 1)),
+                to: Some(NaiveDate::from_ymd(2018, 12, 31)),
+                min_amount: Some(1000500),
+                max_amount: None,
+                sign: Some(Sign::Debit),
+                counterparty: Some(String::from("ACME")),
+                family: Some(String::from("01")),
+                structured_reference: None,
+                grep: Some(String::from("INVOICE")),
+            }
+        );
+        assert_eq!(options.from_json, true);
+        assert_eq!(options.jobs, 4);
         assert_eq!(options.encoding_label.is_some(), true);
         assert_eq!(options.encoding_label.unwrap(), "windows-1252");
     }
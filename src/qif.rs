@@ -0,0 +1,94 @@
+use coda::Coda;
+use utils::format_amount;
+
+// QIF has no standard date format, day first as in the Belgian locale
+const DATE_FORMAT: &str = "%d/%m/%Y";
+
+fn single_line(text: &str) -> String {
+    text.replace('\n', " ").trim().to_string()
+}
+
+fn write_statement(output: &mut Vec<String>, coda: &Coda) {
+    let account = &coda.old_balance.account;
+    output.push(String::from("!Account"));
+    output.push(format!("N{}", account.number()));
+    output.push(String::from("TBank"));
+    if !coda.old_balance.account_description.is_empty() {
+        output.push(format!("D{}", coda.old_balance.account_description));
+    }
+    output.push(String::from("^"));
+    output.push(String::from("!Type:Bank"));
+    for entry in coda.entries() {
+        let movement = entry[0];
+        output.push(format!("D{}", movement.entry_date.format(DATE_FORMAT)));
+        output.push(format!("T{}", format_amount(movement.signed_amount(), '.')));
+        output.push(format!("N{}", movement.bank_reference.trim()));
+        if let Some(counterparty) = Coda::entry_counterparty(&entry) {
+            if let Some(ref name) = counterparty.counterparty_name.as_ref().filter(|n| !n.is_empty()) {
+                output.push(format!("P{}", single_line(name)));
+            }
+        }
+        let memo = match movement.structured_reference() {
+            Some(reference) => reference,
+            None => single_line(&movement.communication),
+        };
+        if !memo.is_empty() {
+            output.push(format!("M{}", memo));
+        }
+        output.push(String::from("^"));
+    }
+}
+
+// One account block per file followed by its transactions
+pub fn to_qif(coda_list: &[Coda]) -> String {
+    let mut output = Vec::new();
+    for coda in coda_list {
+        write_statement(&mut output, coda);
+    }
+    output.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod test_qif {
+    use super::*;
+    use fixtures::parse_coda;
+
+    #[test]
+    fn to_qif_valid() {
+        let coda = parse_coda();
+        let entries = coda.entries().len();
+
+        let actual = to_qif(&[coda]);
+
+        let lines = actual.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            &lines[0..7],
+            &[
+                "!Account",
+                "N435000000080",
+                "TBank",
+                "DKBC-Bedrijfsrekening",
+                "^",
+                "!Type:Bank",
+                "D06/12/2006",
+            ]
+        );
+        assert_eq!(lines[7], "T-2578.250");
+        assert_eq!(lines[8], "NEPIB00048 AWIUBTKAPUO");
+        assert_eq!(lines[9], "MBORDEREAU DE DECOMPTE AVANCES    015 NUMERO D'OPERATI ON 495953");
+        assert_eq!(
+            lines.iter().filter(|l| **l == "^").count(),
+            entries + 1,
+            "There should be one record per entry and one for the account"
+        );
+    }
+
+    #[test]
+    fn to_qif_payee() {
+        let coda = parse_coda();
+
+        let actual = to_qif(&[coda]);
+
+        assert_eq!(actual.contains("\nPECONOLER  S.A.\n"), true, "Counterparty should be the payee");
+    }
+}
@@ -0,0 +1,1102 @@
+extern crate serde_json;
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use coda::{Coda, CommunicationStructure, Movement};
+use csv::{records_to_csv, CsvOptions};
+use utils::{format_amount, signed_amount, Sign};
+
+use errors::*;
+
+// Query over the parsed statements:
+//
+//   <table> [select <column>, ...] [where <condition>] [group by <column>, ...]
+//           [count | sum <column> | avg <column> | min <column> | max <column>, ...]
+//           [order by <column> [asc | desc]] [limit <n>]
+//
+// Tables are movements (the booked entries, one row per entry), details (the detail records of the globalised
+// entries, their amounts add up to the amount of their entry), information and statements. Totals over movements
+// count each amount once, details are only read from their own table. Conditions compare a column with a literal using
+// =, !=, >, >=, <, <=, ~ (contains, case insensitive) or !~, combined with and, or, not and parentheses.
+// Amounts are written as decimals (ie 1000.50), dates as yyyy-mm-dd and texts between double quotes.
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum QueryFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for QueryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<QueryFormat, String> {
+        match s {
+            "table" => Ok(QueryFormat::Table),
+            "csv" => Ok(QueryFormat::Csv),
+            "json" => Ok(QueryFormat::Json),
+            _ => Err(format!("Invalid query format [{}]", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum QueryTable {
+    Movements,
+    Details,
+    Information,
+    Statements,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum ColumnType {
+    Text,
+    Integer,
+    Amount,
+    Date,
+    Bool,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum QueryValue {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    // Thousandths as in the coda file
+    Amount(i64),
+    Text(String),
+    Date(NaiveDate),
+}
+
+impl QueryValue {
+    fn text(text: &str) -> QueryValue {
+        QueryValue::Text(String::from(text.trim()))
+    }
+
+    // Empty optional fields are null
+    fn optional(text: Option<&str>) -> QueryValue {
+        match text.map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            Some(text) => QueryValue::Text(String::from(text)),
+            None => QueryValue::Null,
+        }
+    }
+
+    pub fn format(&self, decimal_separator: char, date_format: &str) -> String {
+        match *self {
+            QueryValue::Null => String::new(),
+            QueryValue::Bool(value) => value.to_string(),
+            QueryValue::Integer(value) => value.to_string(),
+            QueryValue::Amount(value) => format_amount(value, decimal_separator),
+            QueryValue::Text(ref value) => value.clone(),
+            QueryValue::Date(ref value) => value.format(date_format).to_string(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match *self {
+            QueryValue::Null => serde_json::Value::Null,
+            QueryValue::Bool(value) => json!(value),
+            QueryValue::Integer(value) => json!(value),
+            // Exact decimal strings as with --json-amounts decimal, floats would round large sums
+            QueryValue::Amount(value) => json!(format_amount(value, '.')),
+            QueryValue::Text(ref value) => json!(value),
+            QueryValue::Date(ref value) => json!(value.format("%Y-%m-%d").to_string()),
+        }
+    }
+
+    // Nulls come first, values of different types are not comparable
+    fn compare(&self, other: &QueryValue) -> Option<Ordering> {
+        match (self, other) {
+            (&QueryValue::Null, &QueryValue::Null) => Some(Ordering::Equal),
+            (&QueryValue::Null, _) => Some(Ordering::Less),
+            (_, &QueryValue::Null) => Some(Ordering::Greater),
+            (&QueryValue::Bool(a), &QueryValue::Bool(b)) => Some(a.cmp(&b)),
+            (&QueryValue::Integer(a), &QueryValue::Integer(b)) => Some(a.cmp(&b)),
+            (&QueryValue::Amount(a), &QueryValue::Amount(b)) => Some(a.cmp(&b)),
+            (&QueryValue::Text(ref a), &QueryValue::Text(ref b)) => Some(a.cmp(b)),
+            (&QueryValue::Date(ref a), &QueryValue::Date(ref b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+const MOVEMENT_COLUMNS: &[(&str, ColumnType)] = &[
+    ("file_reference", ColumnType::Text),
+    ("account", ColumnType::Text),
+    ("currency", ColumnType::Text),
+    ("statement_number", ColumnType::Text),
+    ("sequence", ColumnType::Text),
+    ("detail_sequence", ColumnType::Text),
+    ("entry", ColumnType::Bool),
+    ("bank_reference", ColumnType::Text),
+    ("value_date", ColumnType::Date),
+    ("entry_date", ColumnType::Date),
+    ("sign", ColumnType::Text),
+    ("amount", ColumnType::Amount),
+    ("signed_amount", ColumnType::Amount),
+    ("transaction_code", ColumnType::Text),
+    ("family", ColumnType::Text),
+    ("communication", ColumnType::Text),
+    ("structured_reference", ColumnType::Text),
+    ("customer_reference", ColumnType::Text),
+    ("counterparty_name", ColumnType::Text),
+    ("counterparty_account", ColumnType::Text),
+    ("counterparty_bic", ColumnType::Text),
+    ("category_purpose", ColumnType::Text),
+    ("purpose", ColumnType::Text),
+    ("r_transaction", ColumnType::Text),
+    ("r_reason", ColumnType::Text),
+];
+
+const INFORMATION_COLUMNS: &[(&str, ColumnType)] = &[
+    ("file_reference", ColumnType::Text),
+    ("account", ColumnType::Text),
+    ("statement_number", ColumnType::Text),
+    ("sequence", ColumnType::Text),
+    ("detail_sequence", ColumnType::Text),
+    ("bank_reference", ColumnType::Text),
+    ("transaction_code", ColumnType::Text),
+    ("family", ColumnType::Text),
+    ("structured", ColumnType::Bool),
+    ("communication", ColumnType::Text),
+];
+
+const STATEMENT_COLUMNS: &[(&str, ColumnType)] = &[
+    ("file_reference", ColumnType::Text),
+    ("creation_date", ColumnType::Date),
+    ("bic", ColumnType::Text),
+    ("account", ColumnType::Text),
+    ("currency", ColumnType::Text),
+    ("statement_number", ColumnType::Text),
+    ("account_holder_name", ColumnType::Text),
+    ("account_description", ColumnType::Text),
+    ("duplicate", ColumnType::Bool),
+    ("old_balance_date", ColumnType::Date),
+    ("old_balance", ColumnType::Amount),
+    ("new_balance_date", ColumnType::Date),
+    ("new_balance", ColumnType::Amount),
+    ("entries", ColumnType::Integer),
+    ("total_debit", ColumnType::Amount),
+    ("total_credit", ColumnType::Amount),
+];
+
+// Rows of the movements and details tables
+fn movement_row(coda: &Coda, movement: &Movement, entry: bool) -> Vec<QueryValue> {
+    vec![
+        QueryValue::text(&coda.header.file_reference),
+        QueryValue::text(coda.old_balance.account.number()),
+        QueryValue::text(coda.old_balance.account.currency()),
+        QueryValue::text(&coda.old_balance.old_sequence),
+        QueryValue::text(&movement.sequence),
+        QueryValue::text(&movement.detail_sequence),
+        QueryValue::Bool(entry),
+        QueryValue::text(&movement.bank_reference),
+        QueryValue::Date(movement.value_date),
+        QueryValue::Date(movement.entry_date),
+        QueryValue::text(match movement.sign() {
+            Sign::Credit => "credit",
+            Sign::Debit => "debit",
+        }),
+        QueryValue::Amount(movement.unsigned_amount() as i64),
+        QueryValue::Amount(movement.signed_amount()),
+        QueryValue::text(&movement.transaction_code),
+        QueryValue::optional(movement.transaction_code.get(1..3)),
+        QueryValue::text(&movement.communication),
+        QueryValue::optional(movement.structured_reference().as_deref()),
+        QueryValue::optional(movement.customer_reference.as_deref()),
+        QueryValue::optional(movement.counterparty_name.as_deref()),
+        QueryValue::optional(movement.counterparty_account.as_ref().map(|a| a.number())),
+        QueryValue::optional(movement.counterparty_bic.as_deref()),
+        QueryValue::optional(movement.category_purpose.as_ref().map(|p| p.code())),
+        QueryValue::optional(movement.purpose.as_ref().map(|p| p.code())),
+        QueryValue::optional(
+            movement
+                .r_transaction
+                .as_ref()
+                .map(|r| format!("{:?}", r).to_lowercase())
+                .as_ref()
+                .map(|r| r.as_str()),
+        ),
+        QueryValue::optional(movement.r_reason.as_ref().map(|r| r.code())),
+    ]
+}
+
+impl QueryTable {
+    fn columns(&self) -> &'static [(&'static str, ColumnType)] {
+        match *self {
+            QueryTable::Movements | QueryTable::Details => MOVEMENT_COLUMNS,
+            QueryTable::Information => INFORMATION_COLUMNS,
+            QueryTable::Statements => STATEMENT_COLUMNS,
+        }
+    }
+
+    fn column(&self, name: &str) -> Result<(usize, ColumnType)> {
+        self.columns()
+            .iter()
+            .position(|&(n, _)| n == name)
+            .map(|i| (i, self.columns()[i].1))
+            .ok_or_else(|| format!("Unknown column [{}] in {:?}", name, self).into())
+    }
+
+    // One row per record, values in the order of the columns
+    fn rows(&self, coda_list: &[Coda]) -> Vec<Vec<QueryValue>> {
+        let mut rows = Vec::new();
+        for coda in coda_list {
+            let file_reference = QueryValue::text(&coda.header.file_reference);
+            let account = QueryValue::text(coda.old_balance.account.number());
+            let statement_number = QueryValue::text(&coda.old_balance.old_sequence);
+            match *self {
+                QueryTable::Movements => for entry in coda.entries() {
+                    rows.push(movement_row(coda, entry[0], true));
+                },
+                QueryTable::Details => for entry in coda.entries() {
+                    rows.extend(entry[1..].iter().map(|movement| movement_row(coda, movement, false)));
+                },
+                QueryTable::Information => for information in &coda.information {
+                    rows.push(vec![
+                        file_reference.clone(),
+                        account.clone(),
+                        statement_number.clone(),
+                        QueryValue::text(&information.sequence),
+                        QueryValue::text(&information.detail_sequence),
+                        QueryValue::text(&information.bank_reference),
+                        QueryValue::text(&information.transaction_code),
+                        QueryValue::optional(information.transaction_code.get(1..3)),
+                        QueryValue::Bool(information.communication_structure == CommunicationStructure::Structured),
+                        QueryValue::text(&information.communication),
+                    ]);
+                },
+                QueryTable::Statements => {
+                    let old_balance = &coda.old_balance;
+                    let new_balance = &coda.new_balance;
+                    rows.push(vec![
+                        file_reference.clone(),
+                        QueryValue::Date(coda.header.creation_date),
+                        QueryValue::text(&coda.header.bic),
+                        account.clone(),
+                        QueryValue::text(old_balance.account.currency()),
+                        statement_number.clone(),
+                        QueryValue::text(&old_balance.account_holder_name),
+                        QueryValue::text(&old_balance.account_description),
+                        QueryValue::Bool(coda.header.duplicate),
+                        QueryValue::Date(old_balance.old_balance_date),
+                        QueryValue::Amount(signed_amount(&old_balance.old_balance_sign, old_balance.old_balance)),
+                        QueryValue::Date(new_balance.new_balance_date),
+                        QueryValue::Amount(signed_amount(&new_balance.new_balance_sign, new_balance.new_balance)),
+                        QueryValue::Integer(coda.entries().len() as i64),
+                        QueryValue::Amount(coda.trailer.total_debit as i64),
+                        QueryValue::Amount(coda.trailer.total_credit as i64),
+                    ]);
+                }
+            }
+        }
+        rows
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Contains,
+    NotContains,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Condition {
+    Compare(usize, Operator, QueryValue),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Query {
+    pub table: QueryTable,
+    pub select: Vec<usize>,
+    pub condition: Option<Condition>,
+    pub group_by: Vec<usize>,
+    pub aggregates: Vec<(Aggregate, Option<usize>)>,
+    pub order_by: Option<(String, bool)>,
+    pub limit: Option<usize>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<QueryValue>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Word(String),
+    Number(String),
+    Text(String),
+    Operator(Operator),
+    Comma,
+    Open,
+    Close,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let chars = query.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            i += 1;
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("Unterminated text in query".into()),
+                    Some(&'"') => break,
+                    Some(&'\\') if chars.get(i + 1).is_some() => {
+                        text.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&c) => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Text(text));
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '-' && next.map_or(false, |n| n.is_ascii_digit())) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == '-') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else {
+            let (operator, length) = match (c, next) {
+                ('!', Some('=')) => (Operator::NotEqual, 2),
+                ('!', Some('~')) => (Operator::NotContains, 2),
+                ('>', Some('=')) => (Operator::GreaterOrEqual, 2),
+                ('<', Some('=')) => (Operator::LessOrEqual, 2),
+                ('=', _) => (Operator::Equal, 1),
+                ('>', _) => (Operator::Greater, 1),
+                ('<', _) => (Operator::Less, 1),
+                ('~', _) => (Operator::Contains, 1),
+                _ => return Err(format!("Unexpected character [{}] in query", c).into()),
+            };
+            tokens.push(Token::Operator(operator));
+            i += length;
+        }
+    }
+    Ok(tokens)
+}
+
+// Signed decimal amount with up to 3 decimals as thousandths
+fn parse_query_amount(s: &str) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let mut parts = s.splitn(2, '.');
+    let units = parts.next()?.parse::<i64>().ok()?;
+    let decimals = parts.next().unwrap_or("");
+    if decimals.len() > 3 || !decimals.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let amount = units
+        .checked_mul(1000)?
+        .checked_add(format!("{:0<3}", decimals).parse::<i64>().ok()?)?;
+    Some(if negative { -amount } else { amount })
+}
+
+// The literal is converted to the type of the column it is compared with
+fn literal(token: &Token, column_type: ColumnType) -> Result<QueryValue> {
+    let text = match *token {
+        Token::Word(ref w) if w.eq_ignore_ascii_case("null") => return Ok(QueryValue::Null),
+        Token::Word(ref w) | Token::Number(ref w) | Token::Text(ref w) => w.clone(),
+        _ => return Err(format!("Expected a value, found {:?}", token).into()),
+    };
+    let value = match column_type {
+        ColumnType::Text => Some(QueryValue::Text(text.clone())),
+        ColumnType::Integer => text.parse::<i64>().ok().map(QueryValue::Integer),
+        ColumnType::Amount => parse_query_amount(&text).map(QueryValue::Amount),
+        ColumnType::Date => NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok().map(QueryValue::Date),
+        ColumnType::Bool => match text.as_str() {
+            "true" => Some(QueryValue::Bool(true)),
+            "false" => Some(QueryValue::Bool(false)),
+            _ => None,
+        },
+    };
+    value.ok_or_else(|| match column_type {
+        ColumnType::Amount => format!("Invalid amount [{}]", text).into(),
+        _ => format!("Invalid {:?} value [{}]", column_type, text).into(),
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    table: QueryTable,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self.peek().cloned().ok_or("Unexpected end of query")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Word(ref w)) => w.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        let is_keyword = self.is_keyword(keyword);
+        if is_keyword {
+            self.position += 1;
+        }
+        is_keyword
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.accept_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("Expected [{}] in query", keyword).into())
+        }
+    }
+
+    fn word(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(format!("Expected a name, found {:?}", token).into()),
+        }
+    }
+
+    fn column(&mut self) -> Result<(usize, ColumnType)> {
+        let name = self.word()?;
+        self.table.column(&name)
+    }
+
+    fn columns(&mut self) -> Result<Vec<usize>> {
+        let mut columns = vec![self.column()?.0];
+        while self.peek() == Some(&Token::Comma) {
+            self.position += 1;
+            columns.push(self.column()?.0);
+        }
+        Ok(columns)
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let mut condition = self.and_condition()?;
+        while self.accept_keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and_condition()?));
+        }
+        Ok(condition)
+    }
+
+    fn and_condition(&mut self) -> Result<Condition> {
+        let mut condition = self.not_condition()?;
+        while self.accept_keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.not_condition()?));
+        }
+        Ok(condition)
+    }
+
+    fn not_condition(&mut self) -> Result<Condition> {
+        if self.accept_keyword("not") {
+            return Ok(Condition::Not(Box::new(self.not_condition()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let condition = self.condition()?;
+            if self.next()? != Token::Close {
+                return Err("Expected [)] in query".into());
+            }
+            return Ok(condition);
+        }
+        let (column, column_type) = self.column()?;
+        let operator = match self.next()? {
+            Token::Operator(operator) => operator,
+            token => return Err(format!("Expected an operator, found {:?}", token).into()),
+        };
+        let value = literal(&self.next()?, column_type)?;
+        let contains = operator == Operator::Contains || operator == Operator::NotContains;
+        if contains && column_type != ColumnType::Text {
+            return Err("~ and !~ only apply to texts".into());
+        }
+        Ok(Condition::Compare(column, operator, value))
+    }
+
+    fn aggregate(&mut self) -> Result<Option<(Aggregate, Option<usize>)>> {
+        let aggregate = match self.peek() {
+            Some(&Token::Word(ref w)) => match w.to_lowercase().as_str() {
+                "count" => Aggregate::Count,
+                "sum" => Aggregate::Sum,
+                "avg" => Aggregate::Avg,
+                "min" => Aggregate::Min,
+                "max" => Aggregate::Max,
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.position += 1;
+        if aggregate == Aggregate::Count {
+            return Ok(Some((aggregate, None)));
+        }
+        let (column, column_type) = self.column()?;
+        let numeric = column_type == ColumnType::Amount || column_type == ColumnType::Integer;
+        if (aggregate == Aggregate::Sum || aggregate == Aggregate::Avg) && !numeric {
+            return Err(format!("{:?} only applies to amounts and numbers", aggregate).into());
+        }
+        Ok(Some((aggregate, Some(column))))
+    }
+
+    fn query(&mut self) -> Result<Query> {
+        let mut query = Query {
+            table: self.table,
+            select: Vec::new(),
+            condition: None,
+            group_by: Vec::new(),
+            aggregates: Vec::new(),
+            order_by: None,
+            limit: None,
+        };
+        if self.accept_keyword("select") {
+            query.select = self.columns()?;
+        }
+        if self.accept_keyword("where") {
+            query.condition = Some(self.condition()?);
+        }
+        if self.accept_keyword("group") {
+            self.expect_keyword("by")?;
+            query.group_by = self.columns()?;
+        }
+        while let Some(aggregate) = self.aggregate()? {
+            query.aggregates.push(aggregate);
+            if self.peek() == Some(&Token::Comma) {
+                self.position += 1;
+            }
+        }
+        if self.accept_keyword("order") {
+            self.expect_keyword("by")?;
+            let column = self.word()?;
+            // Aggregates are ordered by their output name, ie sum(amount)
+            let column = if self.peek() == Some(&Token::Open) {
+                self.position += 1;
+                let name = format!("{}({})", column, self.word()?);
+                if self.next()? != Token::Close {
+                    return Err("Expected [)] in query".into());
+                }
+                name
+            } else {
+                column
+            };
+            let descending = if self.accept_keyword("desc") {
+                true
+            } else {
+                self.accept_keyword("asc");
+                false
+            };
+            query.order_by = Some((column, descending));
+        }
+        if self.accept_keyword("limit") {
+            query.limit = match self.next()? {
+                Token::Number(n) => Some(n.parse().chain_err(|| format!("Invalid limit [{}]", n))?),
+                token => return Err(format!("Expected a number after limit, found {:?}", token).into()),
+            };
+        }
+        if let Some(token) = self.peek() {
+            return Err(format!("Unexpected {:?} in query", token).into());
+        }
+        if !query.select.is_empty() && (!query.group_by.is_empty() || !query.aggregates.is_empty()) {
+            return Err("select can not be used with group by or aggregates".into());
+        }
+        Ok(query)
+    }
+}
+
+pub fn parse_query(text: &str) -> Result<Query> {
+    let tokens = tokenize(text)?;
+    let table = match tokens.first() {
+        Some(&Token::Word(ref w)) if w == "movements" => QueryTable::Movements,
+        Some(&Token::Word(ref w)) if w == "details" => QueryTable::Details,
+        Some(&Token::Word(ref w)) if w == "information" => QueryTable::Information,
+        Some(&Token::Word(ref w)) if w == "statements" => QueryTable::Statements,
+        _ => return Err("Query should start with movements, details, information or statements".into()),
+    };
+    let mut parser = Parser {
+        tokens,
+        position: 1,
+        table,
+    };
+    parser.query().chain_err(|| format!("Invalid query [{}]", text))
+}
+
+impl Condition {
+    fn matches(&self, row: &[QueryValue]) -> bool {
+        match *self {
+            Condition::And(ref a, ref b) => a.matches(row) && b.matches(row),
+            Condition::Or(ref a, ref b) => a.matches(row) || b.matches(row),
+            Condition::Not(ref c) => !c.matches(row),
+            Condition::Compare(column, operator, ref value) => {
+                let actual = &row[column];
+                match operator {
+                    Operator::Contains | Operator::NotContains => {
+                        let contains = match (actual, value) {
+                            (&QueryValue::Text(ref a), &QueryValue::Text(ref v)) => {
+                                a.to_lowercase().contains(&v.to_lowercase())
+                            }
+                            _ => false,
+                        };
+                        contains == (operator == Operator::Contains)
+                    }
+                    Operator::Equal => actual == value,
+                    Operator::NotEqual => actual != value,
+                    // Null is not comparable with a value
+                    _ if *actual == QueryValue::Null || *value == QueryValue::Null => false,
+                    _ => match actual.compare(value) {
+                        Some(ordering) => match operator {
+                            Operator::Greater => ordering == Ordering::Greater,
+                            Operator::GreaterOrEqual => ordering != Ordering::Less,
+                            Operator::Less => ordering == Ordering::Less,
+                            _ => ordering != Ordering::Greater,
+                        },
+                        None => false,
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn aggregate_name(table: QueryTable, aggregate: Aggregate, column: Option<usize>) -> String {
+    match column {
+        Some(column) => format!("{:?}({})", aggregate, table.columns()[column].0).to_lowercase(),
+        None => String::from("count"),
+    }
+}
+
+// Nulls are ignored by all the aggregates but count
+fn aggregate_value(aggregate: Aggregate, column: Option<usize>, rows: &[&Vec<QueryValue>]) -> QueryValue {
+    let values = match column {
+        Some(column) => rows.iter()
+            .map(|row| &row[column])
+            .filter(|v| **v != QueryValue::Null)
+            .collect::<Vec<_>>(),
+        None => return QueryValue::Integer(rows.len() as i64),
+    };
+    let numbers = values.iter().filter_map(|v| match **v {
+        QueryValue::Amount(n) | QueryValue::Integer(n) => Some(n),
+        _ => None,
+    });
+    let is_amount = values.first().map_or(false, |v| matches!(**v, QueryValue::Amount(_)));
+    let number = |n: i64| if is_amount { QueryValue::Amount(n) } else { QueryValue::Integer(n) };
+    match aggregate {
+        Aggregate::Count => QueryValue::Integer(values.len() as i64),
+        Aggregate::Sum => number(numbers.sum()),
+        Aggregate::Avg if values.is_empty() => QueryValue::Null,
+        Aggregate::Avg => number(numbers.sum::<i64>() / values.len() as i64),
+        Aggregate::Min => values
+            .iter()
+            .min_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal))
+            .map_or(QueryValue::Null, |v| (*v).clone()),
+        Aggregate::Max => values
+            .iter()
+            .max_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal))
+            .map_or(QueryValue::Null, |v| (*v).clone()),
+    }
+}
+
+pub fn run_query(query: &Query, coda_list: &[Coda]) -> Result<QueryResult> {
+    let table = query.table;
+    let all_rows = table.rows(coda_list);
+    let rows = all_rows
+        .iter()
+        .filter(|row| query.condition.as_ref().map_or(true, |c| c.matches(row)))
+        .collect::<Vec<_>>();
+
+    let mut result = if query.group_by.is_empty() && query.aggregates.is_empty() {
+        let select = if query.select.is_empty() {
+            (0..table.columns().len()).collect()
+        } else {
+            query.select.clone()
+        };
+        QueryResult {
+            columns: select.iter().map(|&c| String::from(table.columns()[c].0)).collect(),
+            rows: rows.iter()
+                .map(|row| select.iter().map(|&c| row[c].clone()).collect())
+                .collect(),
+        }
+    } else {
+        // Groups are listed in order of first appearance, a group by without aggregate counts the rows
+        let aggregates = if query.aggregates.is_empty() {
+            vec![(Aggregate::Count, None)]
+        } else {
+            query.aggregates.clone()
+        };
+        let mut groups: Vec<(Vec<QueryValue>, Vec<&Vec<QueryValue>>)> = Vec::new();
+        for row in rows {
+            let key = query.group_by.iter().map(|&c| row[c].clone()).collect::<Vec<_>>();
+            match groups.iter().position(|&(ref k, _)| *k == key) {
+                Some(i) => groups[i].1.push(row),
+                None => groups.push((key, vec![row])),
+            }
+        }
+        if groups.is_empty() && query.group_by.is_empty() {
+            groups.push((Vec::new(), Vec::new()));
+        }
+        let mut columns = query
+            .group_by
+            .iter()
+            .map(|&c| String::from(table.columns()[c].0))
+            .collect::<Vec<_>>();
+        columns.extend(aggregates.iter().map(|&(a, c)| aggregate_name(table, a, c)));
+        QueryResult {
+            columns,
+            rows: groups
+                .into_iter()
+                .map(|(mut key, rows)| {
+                    key.extend(aggregates.iter().map(|&(a, c)| aggregate_value(a, c, &rows)));
+                    key
+                })
+                .collect(),
+        }
+    };
+
+    if let Some((ref name, descending)) = query.order_by {
+        let column = result
+            .columns
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| format!("Can not order by [{}], it is not in the result", name))?;
+        result.rows.sort_by(|a, b| {
+            let ordering = a[column].compare(&b[column]).unwrap_or(Ordering::Equal);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+    if let Some(limit) = query.limit {
+        result.rows.truncate(limit);
+    }
+    Ok(result)
+}
+
+pub fn execute(text: &str, coda_list: &[Coda]) -> Result<QueryResult> {
+    let query = parse_query(text)?;
+    run_query(&query, coda_list)
+}
+
+impl QueryResult {
+    // Numbers and amounts are right aligned
+    pub fn to_table(&self) -> String {
+        let cells = self.rows
+            .iter()
+            .map(|row| row.iter().map(|v| v.format('.', "%Y-%m-%d").replace('\n', " ")).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let widths = self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .fold(c.chars().count(), |a, b| a.max(b))
+            })
+            .collect::<Vec<_>>();
+        let right_aligned = (0..self.columns.len())
+            .map(|i| {
+                self.rows
+                    .iter()
+                    .any(|row| matches!(row[i], QueryValue::Amount(_) | QueryValue::Integer(_)))
+            })
+            .collect::<Vec<_>>();
+        let line = |values: &[String]| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let padding = " ".repeat(widths[i] - v.chars().count());
+                    if right_aligned[i] {
+                        format!("{}{}", padding, v)
+                    } else {
+                        format!("{}{}", v, padding)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        let mut output = vec![line(&self.columns)];
+        output.extend(cells.iter().map(|row| line(row)));
+        output.iter().map(|l| format!("{}\n", l)).collect()
+    }
+
+    pub fn to_csv(&self, options: &CsvOptions) -> String {
+        let names = self.columns.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+        let records = self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|v| v.format(options.decimal_separator, &options.date_format))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        records_to_csv(&names, &records, options)
+    }
+
+    // An array of objects, amounts are decimal numbers. The objects are written by hand to keep the
+    // order of the columns, a serde_json map sorts its keys
+    pub fn to_json(&self) -> Result<String> {
+        let mut objects = Vec::new();
+        for row in &self.rows {
+            let mut fields = Vec::new();
+            for (column, value) in self.columns.iter().zip(row.iter()) {
+                fields.push(format!(
+                    "    {}: {}",
+                    serde_json::to_string(column).chain_err(|| "Unable to generate json")?,
+                    serde_json::to_string(&value.to_json()).chain_err(|| "Unable to generate json")?
+                ));
+            }
+            objects.push(format!("  {{\n{}\n  }}", fields.join(",\n")));
+        }
+        if objects.is_empty() {
+            return Ok(String::from("[]"));
+        }
+        Ok(format!("[\n{}\n]", objects.join(",\n")))
+    }
+
+    pub fn format(&self, format: QueryFormat, csv_options: &CsvOptions) -> Result<String> {
+        match format {
+            QueryFormat::Table => Ok(self.to_table()),
+            QueryFormat::Csv => Ok(self.to_csv(csv_options)),
+            QueryFormat::Json => self.to_json().map(|j| format!("{}\n", j)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_query {
+    use super::*;
+    use fixtures::parse_coda_list;
+
+    fn column(result: &QueryResult, name: &str) -> Vec<QueryValue> {
+        let i = result.columns.iter().position(|c| c == name).unwrap();
+        result.rows.iter().map(|row| row[i].clone()).collect()
+    }
+
+    #[test]
+    fn tokenize_valid() {
+        let actual = tokenize("amount >= 1000.50 and name ~ \"A \\\"B\\\"\"").unwrap();
+
+        assert_eq!(
+            actual,
+            vec![
+                Token::Word(String::from("amount")),
+                Token::Operator(Operator::GreaterOrEqual),
+                Token::Number(String::from("1000.50")),
+                Token::Word(String::from("and")),
+                Token::Word(String::from("name")),
+                Token::Operator(Operator::Contains),
+                Token::Text(String::from("A \"B\"")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_query_valid() {
+        let actual = parse_query("movements where amount > 1000 and not sign = \"credit\" limit 5").unwrap();
+
+        assert_eq!(actual.table, QueryTable::Movements);
+        assert_eq!(actual.limit, Some(5));
+        assert_eq!(
+            actual.condition,
+            Some(Condition::And(
+                Box::new(Condition::Compare(11, Operator::Greater, QueryValue::Amount(1000000))),
+                Box::new(Condition::Not(Box::new(Condition::Compare(
+                    10,
+                    Operator::Equal,
+                    QueryValue::Text(String::from("credit"))
+                )))),
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_query_invalid() {
+        assert_eq!(parse_query("payments").is_err(), true, "Unknown table");
+        assert_eq!(parse_query("movements where colour = 1").is_err(), true, "Unknown column");
+        assert_eq!(parse_query("movements where amount > abc").is_err(), true, "Invalid amount");
+        let error = parse_query("movements where amount > 99999999999999999 count").unwrap_err();
+        assert_eq!(
+            error.iter().any(|e| e.to_string() == "Invalid amount [99999999999999999]"),
+            true,
+            "Amount out of range"
+        );
+        assert_eq!(parse_query("movements where value_date > 06/12/2006").is_err(), true, "Invalid date");
+        assert_eq!(parse_query("movements where amount ~ 1").is_err(), true, "Contains on amount");
+        assert_eq!(parse_query("movements sum communication").is_err(), true, "Sum of texts");
+        assert_eq!(parse_query("movements where (amount > 1").is_err(), true, "Missing parenthesis");
+        assert_eq!(parse_query("movements limit 5 extra").is_err(), true, "Trailing tokens");
+    }
+
+    #[test]
+    fn execute_where() {
+        let actual = execute(
+            "movements select sequence, amount where amount > 1000000 and sign = \"debit\"",
+            &parse_coda_list(),
+        ).unwrap();
+
+        assert_eq!(actual.columns, vec!["sequence", "amount"]);
+        assert_eq!(column(&actual, "sequence"), vec![QueryValue::text("0028")]);
+        assert_eq!(column(&actual, "amount"), vec![QueryValue::Amount(2501250000)]);
+    }
+
+    #[test]
+    fn execute_contains_or_null() {
+        let actual = execute(
+            "movements select sequence where counterparty_name ~ \"econoler\" or (sequence = \"0001\" and counterparty_name = null)",
+            &parse_coda_list(),
+        ).unwrap();
+
+        assert_eq!(column(&actual, "sequence"), vec![QueryValue::text("0001")]);
+
+        // The counterparty of entry 0007 is only known from its detail record
+        let actual = execute(
+            "details select sequence, detail_sequence, entry where counterparty_name ~ \"econoler\"",
+            &parse_coda_list(),
+        ).unwrap();
+
+        assert_eq!(
+            actual.rows,
+            vec![vec![QueryValue::text("0007"), QueryValue::text("0001"), QueryValue::Bool(false)]]
+        );
+    }
+
+    #[test]
+    fn execute_movements_count_each_amount_once() {
+        let coda_list = parse_coda_list();
+        let movements = execute("movements sum amount, count", &coda_list).unwrap();
+        let details = execute("details sum amount, count", &coda_list).unwrap();
+        let entries = coda_list[0].entries();
+
+        let (debit, credit) = coda_list[0].entry_totals();
+        assert_eq!(
+            movements.rows,
+            vec![vec![QueryValue::Amount((debit + credit) as i64), QueryValue::Integer(entries.len() as i64)]],
+            "movements should hold the booked entries only"
+        );
+        let detail_count = entries.iter().map(|e| e.len() - 1).sum::<usize>();
+        assert_eq!(
+            column(&details, "count"),
+            vec![QueryValue::Integer(detail_count as i64)],
+            "details should hold the detail records only"
+        );
+    }
+
+    #[test]
+    fn execute_details_add_up_to_their_entry() {
+        let coda_list = parse_coda_list();
+        let actual = execute("details where sequence = \"0007\" sum amount", &coda_list).unwrap();
+
+        let entry = coda_list[0].entries().into_iter().find(|e| e[0].sequence == "0007").unwrap();
+        assert_eq!(entry.len() > 1, true, "entry 0007 should be globalised");
+        assert_eq!(actual.rows, vec![vec![QueryValue::Amount(entry[0].unsigned_amount() as i64)]]);
+    }
+
+    #[test]
+    fn execute_group_by() {
+        let coda_list = parse_coda_list();
+        let actual = execute(
+            "movements group by sign sum amount, count order by sum(amount) desc",
+            &coda_list,
+        ).unwrap();
+
+        let (debit, credit) = coda_list[0].entry_totals();
+        assert_eq!(actual.columns, vec!["sign", "sum(amount)", "count"]);
+        assert_eq!(
+            actual.rows[0],
+            vec![QueryValue::text("credit"), QueryValue::Amount(credit as i64), QueryValue::Integer(29)]
+        );
+        assert_eq!(actual.rows[1][1], QueryValue::Amount(debit as i64));
+    }
+
+    #[test]
+    fn execute_aggregate_without_group() {
+        let actual = execute("statements sum entries, max new_balance", &parse_coda_list()).unwrap();
+
+        assert_eq!(
+            actual.rows,
+            vec![vec![QueryValue::Integer(59), QueryValue::Amount(9405296990)]]
+        );
+    }
+
+    #[test]
+    fn execute_information() {
+        let actual = execute("information where structured = true count", &parse_coda_list()).unwrap();
+
+        assert_eq!(actual.columns, vec!["count"]);
+        assert_eq!(actual.rows.len(), 1);
+    }
+
+    #[test]
+    fn format_valid() {
+        let actual = execute("statements select account, new_balance", &parse_coda_list()).unwrap();
+
+        assert_eq!(
+            actual.to_table(),
+            "account       new_balance\n435000000080  9405296.990\n"
+        );
+        assert_eq!(
+            actual.to_csv(&CsvOptions::default()),
+            "account,new_balance\r\n435000000080,9405296.990\r\n"
+        );
+        assert_eq!(
+            actual.to_json().unwrap(),
+            "[\n  {\n    \"account\": \"435000000080\",\n    \"new_balance\": \"9405296.990\"\n  }\n]"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+extern crate serde_json;
+
+use self::serde_json::Value;
+
+// Version of the json document, increased on every change of its shape
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_ID: &str = "https://github.com/bn3t/coda-rs/schema/coda.schema.json";
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn optional_string() -> Value {
+    json!({ "type": ["string", "null"] })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/definitions/{}", name) })
+}
+
+fn optional(name: &str) -> Value {
+    json!({ "oneOf": [reference(name), { "type": "null" }] })
+}
+
+fn array_of(name: &str) -> Value {
+    json!({ "type": "array", "items": reference(name) })
+}
+
+// All the properties of a struct are written, they are all required even when their value can be null
+fn object(description: &str, properties: Value) -> Value {
+    let required = properties
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(|k| Value::String(k.clone()))
+        .collect::<Vec<Value>>();
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+// Enums with data are externally tagged: an object with the variant name as single key
+fn account_variant(name: &str, with_country: bool) -> Value {
+    let mut properties = json!({ "number": string(), "currency": string() });
+    if with_country {
+        properties["country"] = string();
+    }
+    let mut variant = json!({});
+    variant[name] = object(&format!("{} account", name), properties);
+    object(name, variant)
+}
+
+fn definitions() -> Value {
+    json!({
+        "date": {
+            "type": "string",
+            "format": "date",
+            "pattern": "^[0-9]{4}-[0-9]{2}-[0-9]{2}$",
+            "description": "Date as yyyy-mm-dd",
+        },
+        "amount": {
+            "type": "integer",
+            "minimum": 0,
+            "description": "Amount in thousandths of the currency unit",
+        },
+        "Sign": { "type": "string", "enum": ["Credit", "Debit"] },
+        "CommunicationStructure": { "type": "string", "enum": ["Structured", "Unstructured"] },
+        "RTransaction": {
+            "type": "string",
+            "enum": ["Reject", "Return", "Refund", "Reversal", "Cancellation"],
+        },
+        "IsoCode": {
+            "type": "string",
+            "description": "ISO 20022 external code, unknown codes are kept as is",
+        },
+        "Account": {
+            "oneOf": [
+                account_variant("BelgianAccountNumber", true),
+                account_variant("ForeignAccountNumber", false),
+                account_variant("IBANBelgianAccountNumber", false),
+                account_variant("IBANForeignAccountNumber", false),
+            ],
+        },
+        "Header": object("Header record (0)", json!({
+            "creation_date": reference("date"),
+            "bank_id": string(),
+            "duplicate": boolean(),
+            "file_reference": string(),
+            "name_addressee": string(),
+            "bic": string(),
+            "company_id": string(),
+            "reference": string(),
+            "related_reference": string(),
+            "version": integer(),
+        })),
+        "OldBalance": object("Old balance record (1)", json!({
+            "account": reference("Account"),
+            "old_sequence": string(),
+            "old_balance_sign": reference("Sign"),
+            "old_balance": reference("amount"),
+            "old_balance_date": reference("date"),
+            "account_holder_name": string(),
+            "account_description": string(),
+            "coda_sequence": string(),
+        })),
+        "Movement": object("Movement records (2.1, 2.2 and 2.3), amount holds the sign as first digit", json!({
+            "sequence": string(),
+            "detail_sequence": string(),
+            "bank_reference": string(),
+            "amount": integer(),
+            "value_date": reference("date"),
+            "transaction_code": string(),
+            "communication_structure": reference("CommunicationStructure"),
+            "communication": string(),
+            "entry_date": reference("date"),
+            "statement_number": string(),
+            "customer_reference": optional_string(),
+            "counterparty_bic": optional_string(),
+            "r_transaction": optional("RTransaction"),
+            "r_reason": optional("IsoCode"),
+            "category_purpose": optional("IsoCode"),
+            "purpose": optional("IsoCode"),
+            "counterparty_account": optional("Account"),
+            "counterparty_name": optional_string(),
+            "next_code": boolean(),
+            "link_code": boolean(),
+        })),
+        "Information": object("Information records (3.1, 3.2 and 3.3)", json!({
+            "sequence": string(),
+            "detail_sequence": string(),
+            "bank_reference": string(),
+            "transaction_code": string(),
+            "communication_structure": reference("CommunicationStructure"),
+            "communication": string(),
+        })),
+        "FreeCommunication": object("Free communication records (4)", json!({
+            "sequence": string(),
+            "detail_sequence": string(),
+            "text": string(),
+        })),
+        "NewBalance": object("New balance record (8)", json!({
+            "new_sequence": string(),
+            "new_balance_sign": reference("Sign"),
+            "new_balance": reference("amount"),
+            "new_balance_date": reference("date"),
+        })),
+        "Trailer": object("Trailer record (9)", json!({
+            "number_records": integer(),
+            "total_debit": reference("amount"),
+            "total_credit": reference("amount"),
+        })),
+    })
+}
+
+pub fn coda_schema() -> Value {
+    let mut schema = object("Coda file as written by coda-rs --json", json!({
+        "schema_version": { "type": "integer", "const": SCHEMA_VERSION },
+        "header": reference("Header"),
+        "old_balance": reference("OldBalance"),
+        "movements": array_of("Movement"),
+        "information": array_of("Information"),
+        "free_communications": array_of("FreeCommunication"),
+        "new_balance": reference("NewBalance"),
+        "trailer": reference("Trailer"),
+    }));
+    schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+    schema["$id"] = json!(SCHEMA_ID);
+    schema["title"] = json!("coda-rs json document");
+    schema["definitions"] = definitions();
+    schema
+}
+
+#[cfg(test)]
+mod test_schema {
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::*;
+    use json::to_json;
+    use fixtures::parse_coda;
+
+    fn resolve<'a>(schema: &'a Value, root: &'a Value) -> &'a Value {
+        match schema.get("$ref").and_then(|r| r.as_str()) {
+            Some(r) => &root["definitions"][r.trim_start_matches("#/definitions/")],
+            None => schema,
+        }
+    }
+
+    fn type_matches(name: &str, value: &Value) -> bool {
+        match name {
+            "string" => value.is_string(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "boolean" => value.is_boolean(),
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "null" => value.is_null(),
+            _ => false,
+        }
+    }
+
+    // Checks the subset of json schema used by coda_schema
+    fn check(schema: &Value, value: &Value, root: &Value, path: &str) -> Vec<String> {
+        let schema = resolve(schema, root);
+        let mut errors = Vec::new();
+        if let Some(variants) = schema.get("oneOf").and_then(|v| v.as_array()) {
+            let matching = variants
+                .iter()
+                .filter(|v| check(v, value, root, path).is_empty())
+                .count();
+            if matching != 1 {
+                errors.push(format!("{}: {} variants match", path, matching));
+            }
+            return errors;
+        }
+        let types = match schema.get("type") {
+            Some(&Value::String(ref t)) => vec![t.clone()],
+            Some(&Value::Array(ref ts)) => ts.iter().map(|t| t.as_str().unwrap().to_string()).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|t| type_matches(t, value)) {
+            errors.push(format!("{}: {} is not of type {:?}", path, value, types));
+            return errors;
+        }
+        if let Some(values) = schema.get("enum").and_then(|v| v.as_array()) {
+            if !values.contains(value) {
+                errors.push(format!("{}: {} is not in enum", path, value));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                errors.push(format!("{}: {} is not {}", path, value, expected));
+            }
+        }
+        if let (Some(properties), Some(object)) = (schema.get("properties"), value.as_object()) {
+            for required in schema["required"].as_array().unwrap() {
+                if !object.contains_key(required.as_str().unwrap()) {
+                    errors.push(format!("{}: missing {}", path, required));
+                }
+            }
+            for (key, value) in object {
+                match properties.get(key) {
+                    Some(property) => errors.extend(check(property, value, root, &format!("{}.{}", path, key))),
+                    None => errors.push(format!("{}: unexpected {}", path, key)),
+                }
+            }
+        }
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (i, item) in array.iter().enumerate() {
+                errors.extend(check(items, item, root, &format!("{}[{}]", path, i)));
+            }
+        }
+        errors
+    }
+
+    #[test]
+    fn coda_json_matches_schema() {
+        let coda = parse_coda();
+        let value: Value = serde_json::from_str(&to_json(&coda).unwrap()).unwrap();
+        let schema = coda_schema();
+
+        let errors = check(&schema, &value, &schema, "$");
+
+        assert_eq!(errors, Vec::<String>::new(), "Json output should match the schema");
+    }
+
+    #[test]
+    fn schema_detects_changes() {
+        let coda = parse_coda();
+        let mut value: Value = serde_json::from_str(&to_json(&coda).unwrap()).unwrap();
+        value["header"]["new_field"] = json!(1);
+        value["trailer"].as_object_mut().unwrap().remove("total_debit");
+        let schema = coda_schema();
+
+        let errors = check(&schema, &value, &schema, "$");
+
+        assert_eq!(errors.len(), 2, "Unexpected and missing fields should be reported: {:?}", errors);
+    }
+
+    #[test]
+    fn shipped_schema_is_up_to_date() {
+        let mut shipped = String::new();
+        File::open("schema/coda.schema.json")
+            .unwrap()
+            .read_to_string(&mut shipped)
+            .unwrap();
+        let shipped: Value = serde_json::from_str(&shipped).unwrap();
+
+        assert_eq!(
+            shipped,
+            coda_schema(),
+            "schema/coda.schema.json should be regenerated with coda-rs schema"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+use coda::{Coda, Movement};
+use json::{amount_serde, date_serde};
+use transaction_code::TransactionCode;
+use utils::{format_amount, signed_amount, Sign};
+
+// Summary of the statements of one account across all the files, amounts in thousandths
+#[derive(PartialEq, Debug, Serialize)]
+pub struct AccountStats {
+    pub account: String,
+    pub currency: String,
+    pub statements: u32,
+    #[serde(with = "date_serde")] pub from_date: NaiveDate,
+    #[serde(with = "date_serde")] pub to_date: NaiveDate,
+    // Old balance of the first statement and new balance of the last one
+    #[serde(serialize_with = "amount_serde::serialize_signed")]
+    pub old_balance: i64,
+    #[serde(serialize_with = "amount_serde::serialize_signed")]
+    pub new_balance: i64,
+    pub entries: u32,
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_debit: u64,
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_credit: u64,
+    pub families: Vec<FamilyStats>,
+    pub counterparties: Vec<CounterpartyStats>,
+    pub months: Vec<MonthStats>,
+}
+
+#[derive(PartialEq, Debug, Serialize)]
+pub struct FamilyStats {
+    pub family: String,
+    pub description: String,
+    pub count: u32,
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_debit: u64,
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_credit: u64,
+}
+
+#[derive(PartialEq, Debug, Serialize)]
+pub struct CounterpartyStats {
+    pub name: String,
+    pub count: u32,
+    // Debits and credits together
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub amount: u64,
+}
+
+// Cash-flow of the entries with a value date in the month (yyyy-mm)
+#[derive(PartialEq, Debug, Serialize)]
+pub struct MonthStats {
+    pub month: String,
+    pub count: u32,
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_debit: u64,
+    #[serde(serialize_with = "amount_serde::serialize")]
+    pub total_credit: u64,
+    #[serde(serialize_with = "amount_serde::serialize_signed")]
+    pub net: i64,
+}
+
+fn add_amount(movement: &Movement, total_debit: &mut u64, total_credit: &mut u64) {
+    match movement.sign() {
+        Sign::Debit => *total_debit += movement.unsigned_amount(),
+        Sign::Credit => *total_credit += movement.unsigned_amount(),
+    }
+}
+
+// The counterparty is known by its name, or by its account number when the bank did not give a name
+fn counterparty_name(entry: &[&Movement]) -> Option<String> {
+    let movement = Coda::entry_counterparty(entry)?;
+    match movement.counterparty_name {
+        Some(ref name) if !name.trim().is_empty() => Some(String::from(name.trim())),
+        _ => movement
+            .counterparty_account
+            .as_ref()
+            .map(|account| String::from(account.number())),
+    }
+}
+
+fn account_stats_of(statements: &[&Coda], top: usize) -> AccountStats {
+    let first = statements[0];
+    let last = statements[statements.len() - 1];
+    let mut stats = AccountStats {
+        account: String::from(first.old_balance.account.number()),
+        currency: String::from(first.old_balance.account.currency()),
+        statements: statements.len() as u32,
+        from_date: first.old_balance.old_balance_date,
+        to_date: last.new_balance.new_balance_date,
+        old_balance: signed_amount(&first.old_balance.old_balance_sign, first.old_balance.old_balance),
+        new_balance: signed_amount(&last.new_balance.new_balance_sign, last.new_balance.new_balance),
+        entries: 0,
+        total_debit: 0,
+        total_credit: 0,
+        families: Vec::new(),
+        counterparties: Vec::new(),
+        months: Vec::new(),
+    };
+    let mut families: BTreeMap<String, FamilyStats> = BTreeMap::new();
+    let mut counterparties: BTreeMap<String, CounterpartyStats> = BTreeMap::new();
+    let mut months: BTreeMap<String, MonthStats> = BTreeMap::new();
+    for coda in statements {
+        for entry in coda.entries() {
+            let movement = entry[0];
+            stats.entries += 1;
+            add_amount(movement, &mut stats.total_debit, &mut stats.total_credit);
+
+            let code = TransactionCode::parse(&movement.transaction_code).ok();
+            let family = families
+                .entry(code.as_ref().map_or(String::from("--"), |c| c.family.clone()))
+                .or_insert_with(|| FamilyStats {
+                    family: code.as_ref().map_or(String::from("--"), |c| c.family.clone()),
+                    description: String::from(code.as_ref().map_or("Unknown family", |c| c.family_description())),
+                    count: 0,
+                    total_debit: 0,
+                    total_credit: 0,
+                });
+            family.count += 1;
+            add_amount(movement, &mut family.total_debit, &mut family.total_credit);
+
+            if let Some(name) = counterparty_name(&entry) {
+                let counterparty = counterparties
+                    .entry(name.to_uppercase())
+                    .or_insert_with(|| CounterpartyStats {
+                        name,
+                        count: 0,
+                        amount: 0,
+                    });
+                counterparty.count += 1;
+                counterparty.amount += movement.unsigned_amount();
+            }
+
+            let month_key = movement.value_date.format("%Y-%m").to_string();
+            let month = months.entry(month_key.clone()).or_insert_with(|| MonthStats {
+                month: month_key,
+                count: 0,
+                total_debit: 0,
+                total_credit: 0,
+                net: 0,
+            });
+            month.count += 1;
+            month.net += movement.signed_amount();
+            add_amount(movement, &mut month.total_debit, &mut month.total_credit);
+        }
+    }
+    stats.families = families.into_values().collect();
+    let mut counterparties = counterparties.into_values().collect::<Vec<_>>();
+    counterparties.sort_by_key(|c| ::std::cmp::Reverse(c.amount));
+    counterparties.truncate(top);
+    stats.counterparties = counterparties;
+    stats.months = months.into_values().collect();
+    stats
+}
+
+// One summary per account and currency, sorted by account; the statements of an account are taken in date order
+pub fn account_stats(coda_list: &[Coda], top: usize) -> Vec<AccountStats> {
+    let mut accounts: BTreeMap<(String, String), Vec<&Coda>> = BTreeMap::new();
+    for coda in coda_list {
+        let account = &coda.old_balance.account;
+        accounts
+            .entry((String::from(account.number()), String::from(account.currency())))
+            .or_default()
+            .push(coda);
+    }
+    accounts
+        .into_values()
+        .map(|mut statements| {
+            statements.sort_by(|a, b| {
+                (a.old_balance.old_balance_date, &a.old_balance.old_sequence)
+                    .cmp(&(b.old_balance.old_balance_date, &b.old_balance.old_sequence))
+            });
+            account_stats_of(&statements, top)
+        })
+        .collect()
+}
+
+fn amount(amount: i64) -> String {
+    format_amount(amount, '.')
+}
+
+pub fn to_text(stats_list: &[AccountStats]) -> String {
+    let mut text = String::new();
+    for stats in stats_list {
+        text += &format!("Account {} {}\n", stats.account, stats.currency);
+        text += &format!(
+            "  Statements       {:>18}   {} - {}\n",
+            stats.statements,
+            stats.from_date.format("%Y-%m-%d"),
+            stats.to_date.format("%Y-%m-%d")
+        );
+        text += &format!("  Old balance      {:>18}\n", amount(stats.old_balance));
+        text += &format!("  New balance      {:>18}\n", amount(stats.new_balance));
+        text += &format!("  Entries          {:>18}\n", stats.entries);
+        text += &format!("  Total debit      {:>18}\n", amount(stats.total_debit as i64));
+        text += &format!("  Total credit     {:>18}\n", amount(stats.total_credit as i64));
+
+        text += &format!("\n  {:<6} {:>6} {:>18} {:>18}  {}\n", "Family", "Count", "Debit", "Credit", "Description");
+        for family in &stats.families {
+            text += &format!(
+                "  {:<6} {:>6} {:>18} {:>18}  {}\n",
+                family.family,
+                family.count,
+                amount(family.total_debit as i64),
+                amount(family.total_credit as i64),
+                family.description
+            );
+        }
+
+        text += &format!("\n  {:<37} {:>6} {:>18}\n", "Counterparty", "Count", "Amount");
+        for counterparty in &stats.counterparties {
+            text += &format!(
+                "  {:<37} {:>6} {:>18}\n",
+                counterparty.name.chars().take(37).collect::<String>(),
+                counterparty.count,
+                amount(counterparty.amount as i64)
+            );
+        }
+
+        text += &format!("\n  {:<7} {:>6} {:>18} {:>18} {:>18}\n", "Month", "Count", "Debit", "Credit", "Net");
+        for month in &stats.months {
+            text += &format!(
+                "  {:<7} {:>6} {:>18} {:>18} {:>18}\n",
+                month.month,
+                month.count,
+                amount(month.total_debit as i64),
+                amount(month.total_credit as i64),
+                amount(month.net)
+            );
+        }
+        text += "\n";
+    }
+    text
+}
+
+#[cfg(test)]
+mod test_stats {
+    use super::*;
+    use fixtures::parse_coda;
+
+    #[test]
+    fn account_stats_single_file() {
+        let coda = parse_coda();
+        let (debit, credit) = coda.entry_totals();
+
+        let actual = account_stats(&[coda], 3);
+
+        assert_eq!(actual.len(), 1);
+        let stats = &actual[0];
+        assert_eq!(stats.account, "435000000080");
+        assert_eq!(stats.currency, "EUR");
+        assert_eq!(stats.statements, 1);
+        assert_eq!(stats.entries, 59);
+        assert_eq!((stats.total_debit, stats.total_credit), (debit, credit));
+        assert_eq!(stats.new_balance, 9405296990);
+        assert_eq!(
+            stats.new_balance - stats.old_balance,
+            credit as i64 - debit as i64,
+            "Balances should follow the entries"
+        );
+    }
+
+    #[test]
+    fn account_stats_families_and_months() {
+        let actual = account_stats(&[parse_coda()], 3);
+
+        let stats = &actual[0];
+        assert_eq!(stats.families.iter().map(|f| f.count).sum::<u32>(), stats.entries);
+        assert_eq!(
+            stats.families.iter().map(|f| f.total_debit).sum::<u64>(),
+            stats.total_debit
+        );
+        assert_eq!(stats.months.iter().map(|m| m.count).sum::<u32>(), stats.entries);
+        assert_eq!(
+            stats.months.iter().map(|m| m.net).sum::<i64>(),
+            stats.total_credit as i64 - stats.total_debit as i64
+        );
+    }
+
+    #[test]
+    fn account_stats_top_counterparties() {
+        let actual = account_stats(&[parse_coda()], 3);
+
+        let counterparties = &actual[0].counterparties;
+        assert_eq!(counterparties.len(), 3, "Only the top counterparties should be kept");
+        assert_eq!(
+            counterparties.windows(2).all(|w| w[0].amount >= w[1].amount),
+            true,
+            "Counterparties should be sorted by amount"
+        );
+    }
+
+    #[test]
+    fn account_stats_several_statements() {
+        let mut second = parse_coda();
+        second.old_balance.old_balance_date = second.new_balance.new_balance_date.succ();
+        second.new_balance.new_balance_date = second.old_balance.old_balance_date.succ();
+
+        let actual = account_stats(&[second, parse_coda()], 10);
+
+        assert_eq!(actual.len(), 1, "Statements of the same account should be merged");
+        let stats = &actual[0];
+        assert_eq!(stats.statements, 2);
+        assert_eq!(stats.entries, 118);
+        assert_eq!(stats.from_date, parse_coda().old_balance.old_balance_date);
+        assert_eq!(stats.to_date, stats.from_date.succ().succ().succ());
+    }
+
+    #[test]
+    fn to_text_valid() {
+        let actual = to_text(&account_stats(&[parse_coda()], 3));
+
+        assert_eq!(actual.starts_with("Account 435000000080 EUR\n"), true);
+        assert_eq!(actual.contains("  New balance             9405296.990\n"), true, "{}", actual);
+    }
+}
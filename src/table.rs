@@ -0,0 +1,265 @@
+extern crate terminal_size;
+
+use std::env;
+use std::io::{stdout, IsTerminal};
+use std::str::FromStr;
+
+use coda::{Coda, Movement};
+use table::terminal_size::{terminal_size, Width};
+use utils::{format_amount, signed_amount, Sign};
+
+const DEFAULT_WIDTH: usize = 120;
+const MIN_TEXT_WIDTH: usize = 10;
+const SEPARATOR: &str = "  ";
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<ColorChoice, String> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Invalid color choice [{}]", s)),
+        }
+    }
+}
+
+impl ColorChoice {
+    // Colors are only used in auto mode when writing to a terminal
+    pub fn enabled(&self) -> bool {
+        match *self {
+            ColorChoice::Auto => stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// When the output is not a terminal, the COLUMNS environment variable is used if set
+pub fn terminal_width() -> usize {
+    match terminal_size() {
+        Some((Width(width), _)) => width as usize,
+        None => env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(DEFAULT_WIDTH),
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    let text = text.replace('\n', " ");
+    let text = text.trim();
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut truncated = text.chars().take(width - 1).collect::<String>();
+        truncated.push('…');
+        truncated
+    }
+}
+
+fn pad_right(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(text.chars().count())))
+}
+
+fn pad_left(text: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(text.chars().count())), text)
+}
+
+struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn amount(&self, text: &str, sign: &Sign) -> String {
+        match *sign {
+            Sign::Credit => self.paint(text, GREEN),
+            Sign::Debit => self.paint(text, RED),
+        }
+    }
+}
+
+fn counterparty(movement: &Movement) -> String {
+    let name = movement.counterparty_name.clone().unwrap_or_default();
+    match movement.counterparty_account {
+        Some(ref account) if name.is_empty() => String::from(account.number()),
+        Some(ref account) => format!("{} ({})", name, account.number()),
+        None => name,
+    }
+}
+
+fn communication(movement: &Movement) -> String {
+    match movement.structured_reference() {
+        Some(reference) => reference,
+        None => movement.communication.clone(),
+    }
+}
+
+fn write_header(output: &mut Vec<String>, painter: &Painter, coda: &Coda) {
+    let header = &coda.header;
+    let old_balance = &coda.old_balance;
+    let new_balance = &coda.new_balance;
+    let currency = old_balance.account.currency();
+    let duplicate = if header.duplicate { " (duplicate)" } else { "" };
+
+    output.push(painter.paint(
+        &format!(
+            "File {} created {} by {} for {}{}",
+            header.file_reference.trim(),
+            header.creation_date,
+            header.bic,
+            header.name_addressee,
+            duplicate
+        ),
+        BOLD,
+    ));
+    output.push(format!(
+        "Account {} {} - {} - {} - statement {}",
+        old_balance.account.number(),
+        currency,
+        old_balance.account_holder_name,
+        old_balance.account_description,
+        old_balance.old_sequence
+    ));
+    let opening = format_amount(signed_amount(&old_balance.old_balance_sign, old_balance.old_balance), '.');
+    let closing = format_amount(signed_amount(&new_balance.new_balance_sign, new_balance.new_balance), '.');
+    let width = opening.len().max(closing.len());
+    output.push(format!(
+        "Opening balance {} {} {}",
+        old_balance.old_balance_date,
+        painter.amount(&pad_left(&opening, width), &old_balance.old_balance_sign),
+        currency
+    ));
+    output.push(format!(
+        "Closing balance {} {} {}",
+        new_balance.new_balance_date,
+        painter.amount(&pad_left(&closing, width), &new_balance.new_balance_sign),
+        currency
+    ));
+}
+
+// Sequence, dates and amount have a fixed width, counterparty and communication share what is left of the
+// terminal width
+fn write_movements(output: &mut Vec<String>, painter: &Painter, coda: &Coda, width: usize) {
+    let amounts = coda.movements
+        .iter()
+        .map(|m| format_amount(m.signed_amount(), '.'))
+        .collect::<Vec<String>>();
+    let amount_width = amounts.iter().map(|a| a.len()).max().unwrap_or(0).max("Amount".len());
+    let fixed_width = 9 + 10 + 10 + amount_width + SEPARATOR.len() * 5;
+    let text_width = width.saturating_sub(fixed_width).max(MIN_TEXT_WIDTH * 2);
+    let counterparty_width = (text_width / 3).max(MIN_TEXT_WIDTH);
+    let communication_width = (text_width - counterparty_width).max(MIN_TEXT_WIDTH);
+
+    let columns = [
+        pad_right("Sequence", 9),
+        pad_right("Entry", 10),
+        pad_right("Value", 10),
+        pad_left("Amount", amount_width),
+        pad_right(&truncate("Counterparty", counterparty_width), counterparty_width),
+        String::from("Communication"),
+    ];
+    output.push(painter.paint(&columns.join(SEPARATOR), BOLD));
+    for (movement, amount) in coda.movements.iter().zip(amounts.iter()) {
+        let columns = [
+            format!("{}/{}", movement.sequence, movement.detail_sequence),
+            movement.entry_date.to_string(),
+            movement.value_date.to_string(),
+            painter.amount(&pad_left(amount, amount_width), &movement.sign()),
+            pad_right(&truncate(&counterparty(movement), counterparty_width), counterparty_width),
+            truncate(&communication(movement), communication_width),
+        ];
+        output.push(columns.join(SEPARATOR).trim_end().to_string());
+    }
+}
+
+pub fn to_table(coda_list: &[Coda], width: usize, color: bool) -> String {
+    let painter = Painter { color };
+    let mut output = Vec::new();
+    for (i, coda) in coda_list.iter().enumerate() {
+        if i > 0 {
+            output.push(String::new());
+        }
+        write_header(&mut output, &painter, coda);
+        output.push(String::new());
+        write_movements(&mut output, &painter, coda, width);
+    }
+    output.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod test_table {
+    use super::*;
+    use fixtures::parse_coda_list;
+
+    #[test]
+    fn to_table_valid() {
+        let actual = to_table(&parse_coda_list(), 100, false);
+
+        let lines = actual.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "File 00099449 created 2006-12-06 by KREDBEBB for Testgebruiker21");
+        assert_eq!(
+            lines[1],
+            "Account 435000000080 EUR - Testgebruiker21 - KBC-Bedrijfsrekening - statement 001"
+        );
+        assert_eq!(lines[2], "Opening balance 2006-12-06       0.000 EUR");
+        assert_eq!(lines[3], "Closing balance 2006-12-07 9405296.990 EUR");
+        assert_eq!(lines[5].starts_with("Sequence   Entry       Value"), true, "Movements header");
+        assert_eq!(lines[6].starts_with("0001/0000  2006-12-06  2006-12-06"), true, "First movement");
+        assert_eq!(lines[6].contains("-2578.250"), true, "Signed amount");
+    }
+
+    #[test]
+    fn to_table_width() {
+        for width in &[60, 100, 200] {
+            let actual = to_table(&parse_coda_list(), *width, false);
+
+            let longest = actual.lines().skip(5).map(|l| l.chars().count()).max().unwrap();
+            assert_eq!(longest <= (*width).max(85), true, "Lines should fit in {} columns", width);
+        }
+    }
+
+    #[test]
+    fn to_table_colors() {
+        let actual = to_table(&parse_coda_list(), 100, true);
+
+        assert_eq!(actual.contains("\x1b[31m   -2578.250\x1b[0m"), true, "Debit should be red");
+        assert_eq!(actual.contains("\x1b[32m      11.210\x1b[0m"), true, "Credit should be green");
+        assert_eq!(to_table(&parse_coda_list(), 100, false).contains("\x1b["), false, "No colors");
+    }
+
+    #[test]
+    fn truncate_valid() {
+        assert_eq!(truncate("ABCDEFGHIJKL", 5), "ABCD…");
+        assert_eq!(truncate("ABC", 5), "ABC");
+        assert_eq!(truncate("LINE 1\nLINE 2", 20), "LINE 1 LINE 2");
+    }
+
+    #[test]
+    fn color_choice_from_str() {
+        assert_eq!("always".parse::<ColorChoice>(), Ok(ColorChoice::Always));
+        assert_eq!("never".parse::<ColorChoice>(), Ok(ColorChoice::Never));
+        assert_eq!("sometimes".parse::<ColorChoice>().is_err(), true, "sometimes should not be ok");
+    }
+}
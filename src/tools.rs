@@ -1,10 +1,108 @@
+extern crate serde;
+
+use std::collections::BTreeMap;
+
 use coda::Coda;
+use db::ImportSummary;
 use json;
+use json::{JsonFormat, JsonShape};
+use tools::serde::Serialize;
+use validation::ValidationReport;
 
 use errors::*;
 
-pub fn print_as_json(coda: &Coda) -> Result<()> {
-    let j = json::to_json(coda).chain_err(|| "Could not make json")?;
+pub fn print_as_json<T: Serialize>(value: &T) -> Result<()> {
+    let j = json::to_json(value).chain_err(|| "Could not make json")?;
     println!("{}", j);
     Ok(())
 }
+
+// The default shape is written as is, other shapes go through a json value (its keys are then sorted)
+pub fn print_list_as_json<T: Serialize>(values: &[T], format: JsonFormat, shape: &JsonShape) -> Result<()> {
+    if !shape.is_default() {
+        let values = values
+            .iter()
+            .map(|v| shape.to_value(v))
+            .collect::<Result<Vec<_>>>()?;
+        return print_list_as_json(&values, format, &JsonShape::default());
+    }
+    match format {
+        JsonFormat::Pretty => {
+            for value in values {
+                print_as_json(value)?;
+            }
+        }
+        JsonFormat::Array => {
+            let j = json::to_json_array(values).chain_err(|| "Could not make json")?;
+            println!("{}", j);
+        }
+        JsonFormat::Ndjson => {
+            for value in values {
+                let j = json::to_json_compact(value).chain_err(|| "Could not make json")?;
+                println!("{}", j);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn print_validation(filename: &str, report: &ValidationReport) {
+    for finding in &report.findings {
+        eprintln!(
+            "{:?}: {}: {}: {}",
+            finding.severity, filename, finding.location, finding.message
+        );
+    }
+}
+
+pub fn print_import_summary(summary: &ImportSummary) {
+    for filename in &summary.imported {
+        println!("Imported: {}", filename);
+    }
+    for filename in &summary.skipped {
+        println!("Skipped (already imported): {}", filename);
+    }
+}
+
+// Written on stderr as the validation findings, the chosen export stays alone on stdout
+pub fn print_r_transactions(coda: &Coda) {
+    for movement in &coda.movements {
+        if let Some(ref r_transaction) = movement.r_transaction {
+            let reason = match movement.r_reason {
+                Some(ref reason) => format!("{} {}", reason.code(), reason.description()),
+                None => String::from("No reason"),
+            };
+            eprintln!(
+                "{} {}/{}: {} - {}",
+                coda.header.file_reference.trim(),
+                movement.sequence,
+                movement.detail_sequence,
+                r_transaction.description(),
+                reason
+            );
+        }
+    }
+}
+
+// Count movements per category purpose or purpose code, movements without a code are counted under "----"
+pub fn print_purpose_groups(coda_list: &[Coda], group_by: &str) -> Result<()> {
+    let mut groups: BTreeMap<String, (String, u32)> = BTreeMap::new();
+    for movement in coda_list.iter().flat_map(|coda| coda.movements.iter()) {
+        let (code, description) = match group_by {
+            "category-purpose" => match movement.category_purpose {
+                Some(ref p) => (String::from(p.code()), String::from(p.description())),
+                None => (String::from("----"), String::from("No category purpose")),
+            },
+            "purpose" => match movement.purpose {
+                Some(ref p) => (String::from(p.code()), String::from(p.description())),
+                None => (String::from("----"), String::from("No purpose")),
+            },
+            _ => return Err(format!("Invalid group by value [{}]", group_by).into()),
+        };
+        groups.entry(code).or_insert((description, 0)).1 += 1;
+    }
+    for (code, (description, count)) in groups {
+        println!("{} {:>6} {}", code, count, description);
+    }
+    Ok(())
+}
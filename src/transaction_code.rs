@@ -0,0 +1,91 @@
+use errors::*;
+use utils::{parse_field, parse_str, parse_u8};
+
+// Transaction code of movement and information records (8 positions): type, family, transaction and category
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct TransactionCode {
+    pub transaction_type: u8, //': (slice(0, 1), int),
+    pub family: String,       //': (slice(1, 3), str),
+    pub transaction: String,  //': (slice(3, 5), str),
+    pub category: String,     //': (slice(5, 8), str),
+}
+
+impl TransactionCode {
+    pub fn parse(code: &str) -> Result<TransactionCode> {
+        Ok(TransactionCode {
+            transaction_type: parse_field(code, 0..1, parse_u8).chain_err(|| "Could not parse transaction_type")?,
+            family: parse_field(code, 1..3, parse_str).chain_err(|| "Could not parse family")?,
+            transaction: parse_field(code, 3..5, parse_str).chain_err(|| "Could not parse transaction")?,
+            category: parse_field(code, 5..8, parse_str).chain_err(|| "Could not parse category")?,
+        })
+    }
+
+    pub fn type_description(&self) -> &str {
+        match self.transaction_type {
+            0 => "Simple amount without detailed data",
+            1 => "Amount as totalised by the customer",
+            2 => "Amount as totalised by the bank",
+            3 => "Simple amount with detailed data",
+            5 => "Detail of amount as totalised by the customer",
+            6 => "Detail of amount as totalised by the bank",
+            7 => "Detail of amount as totalised by the customer, for information",
+            8 => "Detail of amount as totalised by the bank, for information",
+            9 => "Detail of simple amount with detailed data, for information",
+            _ => "Unknown type",
+        }
+    }
+
+    pub fn family_description(&self) -> &str {
+        match self.family.as_str() {
+            "01" => "Domestic or local SEPA credit transfers",
+            "02" => "Instant SEPA credit transfers",
+            "03" => "Cheques",
+            "04" => "Cards",
+            "05" => "Direct debit",
+            "07" => "Domestic commercial paper",
+            "09" => "Counter transactions",
+            "11" => "Securities",
+            "13" => "Credit",
+            "30" => "Various transactions",
+            "35" => "Closing (periodical settlements for interest, costs, ...)",
+            "41" => "International credit transfers - non-SEPA credit transfers",
+            "43" => "Foreign cheques",
+            "47" => "Foreign commercial paper",
+            "80" => "Separately charged costs and provisions",
+            _ => "Unknown family",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_transaction_code {
+    use super::TransactionCode;
+
+    #[test]
+    fn parse_transaction_code_valid() {
+        let actual = TransactionCode::parse("00150000");
+
+        assert_eq!(actual.is_ok(), true, "TransactionCode should be ok");
+        let actual = actual.unwrap();
+        assert_eq!(actual.transaction_type, 0, "transaction_type should be 0");
+        assert_eq!(actual.family, "01", "family should be '01'");
+        assert_eq!(actual.transaction, "50", "transaction should be '50'");
+        assert_eq!(actual.category, "000", "category should be '000'");
+        assert_eq!(actual.family_description(), "Domestic or local SEPA credit transfers");
+        assert_eq!(actual.type_description(), "Simple amount without detailed data");
+    }
+
+    #[test]
+    fn parse_transaction_code_invalid() {
+        let actual = TransactionCode::parse("X0150000");
+
+        assert_eq!(actual.is_ok(), false, "TransactionCode should not be ok");
+    }
+
+    #[test]
+    fn transaction_code_unknown_family() {
+        let actual = TransactionCode::parse("09900000").unwrap();
+
+        assert_eq!(actual.family_description(), "Unknown family");
+    }
+}
@@ -58,6 +58,30 @@ pub fn parse_duplicate(s: &str) -> Result<bool> {
     }
 }
 
+// Next / link codes, a blank or missing zone (short record) means there is no continuation
+pub fn parse_code(s: &str) -> Result<bool> {
+    match s.trim() {
+        "1" => Ok(true),
+        "0" | "" => Ok(false),
+        _ => Err(format!("Invalid code value [{}]", s).into()),
+    }
+}
+
+// Apply a sign to an amount as found in the coda file (3 decimals)
+pub fn signed_amount(sign: &Sign, amount: u64) -> i64 {
+    match *sign {
+        Sign::Credit => amount as i64,
+        Sign::Debit => -(amount as i64),
+    }
+}
+
+// Format an amount in thousandths (3 decimals as in the coda file) as a decimal string
+pub fn format_amount(amount: i64, decimal_separator: char) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.abs();
+    format!("{}{}{}{:03}", sign, amount / 1000, decimal_separator, amount % 1000)
+}
+
 pub fn parse_field<T>(
     line: &str,
     range: Range<usize>,
@@ -158,6 +182,35 @@ mod test_parse_utils {
         assert_eq!(actual.is_ok(), false, "Duplicate 'B' should not be ok");
     }
 
+    #[test]
+    fn signed_amount_valid() {
+        assert_eq!(signed_amount(&Sign::Credit, 2578250), 2578250);
+        assert_eq!(signed_amount(&Sign::Debit, 2578250), -2578250);
+    }
+
+    #[test]
+    fn format_amount_valid() {
+        assert_eq!(format_amount(2578250, '.'), "2578.250");
+        assert_eq!(format_amount(-2578250, ','), "-2578,250");
+        assert_eq!(format_amount(5, '.'), "0.005");
+        assert_eq!(format_amount(0, '.'), "0.000");
+    }
+
+    #[test]
+    fn parse_code_valid() {
+        assert_eq!(parse_code("1").unwrap(), true, "Code '1' should be true");
+        assert_eq!(parse_code("0").unwrap(), false, "Code '0' should be false");
+        assert_eq!(parse_code(" ").unwrap(), false, "Blank code should be false");
+        assert_eq!(parse_code("").unwrap(), false, "Missing code should be false");
+    }
+
+    #[test]
+    fn parse_code_invalid() {
+        let actual = parse_code("2");
+
+        assert_eq!(actual.is_ok(), false, "Code '2' should not be ok");
+    }
+
     #[test]
     fn parse_u8_valid() {
         let actual = parse_u8("2");
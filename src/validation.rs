@@ -0,0 +1,347 @@
+use coda::{Account, Coda, Movement};
+
+#[derive(PartialEq, Debug, Serialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(PartialEq, Debug, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub findings: Vec<Finding>,
+}
+
+impl ValidationReport {
+    pub fn warning(&mut self, location: String, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            location,
+            message,
+        });
+    }
+
+    pub fn error(&mut self, location: String, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            location,
+            message,
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+}
+
+// Active ISO 4217 alphabetic currency codes.
+const CURRENCY_CODES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT", "BGN", "BHD", "BIF",
+    "BMD", "BND", "BOB", "BOV", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD", "CDF", "CHE", "CHF", "CHW", "CLF",
+    "CLP", "CNY", "COP", "COU", "CRC", "CUC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP", "DZD", "EGP", "ERN", "ETB",
+    "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR",
+    "ILS", "INR", "IQD", "IRR", "ISK", "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD",
+    "KZT", "LAK", "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU", "MUR",
+    "MVR", "MWK", "MXN", "MXV", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK",
+    "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP",
+    "SLE", "SLL", "SOS", "SRD", "SSP", "STN", "SVC", "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD",
+    "TWD", "TZS", "UAH", "UGX", "USD", "USN", "UYI", "UYU", "UYW", "UZS", "VED", "VES", "VND", "VUV", "WST", "XAF",
+    "XAG", "XAU", "XBA", "XBB", "XBC", "XBD", "XCD", "XDR", "XOF", "XPD", "XPF", "XPT", "XSU", "XTS", "XUA", "XXX",
+    "YER", "ZAR", "ZMW", "ZWL",
+];
+
+// ISO 3166-1 alpha-2 country codes.
+const COUNTRY_CODES: &[&str] = &[
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ", "BA", "BB", "BD",
+    "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS", "BT", "BV", "BW", "BY", "BZ", "CA",
+    "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN", "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE",
+    "DJ", "DK", "DM", "DO", "DZ", "EC", "EE", "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA",
+    "GB", "GD", "GE", "GF", "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK",
+    "HM", "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM", "JO", "JP",
+    "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC", "LI", "LK", "LR", "LS", "LT",
+    "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK", "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS",
+    "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA", "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ",
+    "OM", "PA", "PE", "PF", "PG", "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS",
+    "RU", "RW", "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS", "ST",
+    "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO", "TR", "TT", "TV", "TW",
+    "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI", "VN", "VU", "WF", "WS", "YE", "YT", "ZA",
+    "ZM", "ZW",
+];
+
+pub fn currency_is_valid(currency: &str) -> bool {
+    CURRENCY_CODES.contains(&currency)
+}
+
+pub fn country_is_valid(country: &str) -> bool {
+    COUNTRY_CODES.contains(&country)
+}
+
+// Check the structure of a BIC (ISO 9362): 4 letters bank code, a valid country code, 2 alphanumeric location
+// characters and an optional 3 alphanumeric branch code.
+pub fn bic_is_valid(bic: &str) -> bool {
+    if (bic.len() != 8 && bic.len() != 11) || !bic.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return false;
+    }
+    bic[0..4].chars().all(|c| c.is_ascii_uppercase()) && country_is_valid(&bic[4..6])
+}
+
+// Check an IBAN using the ISO 13616 mod-97 check digits.
+pub fn iban_is_valid(iban: &str) -> bool {
+    let iban: String = iban.chars().filter(|c| !c.is_whitespace()).collect();
+    if iban.len() < 15 || iban.len() > 34 || !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    if !iban.chars().take(2).all(|c| c.is_ascii_uppercase()) || !iban.chars().skip(2).take(2).all(|c| c.is_ascii_digit())
+    {
+        return false;
+    }
+    if iban.starts_with("BE") && iban.len() != 16 {
+        return false;
+    }
+    let rearranged = format!("{}{}", &iban[4..], &iban[0..4]);
+    let remainder = rearranged.chars().fold(0u32, |acc, c| {
+        let value = c.to_digit(36).unwrap();
+        if value < 10 {
+            (acc * 10 + value) % 97
+        } else {
+            (acc * 100 + value) % 97
+        }
+    });
+    remainder == 1
+}
+
+// Check a 12 digit Belgian account number (BBAN): the last two digits are the first ten modulo 97 (97 when 0).
+pub fn belgian_account_is_valid(number: &str) -> bool {
+    if number.len() != 12 || !number.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let base = number[0..10].parse::<u64>().unwrap();
+    let check = number[10..12].parse::<u64>().unwrap();
+    let expected = match base % 97 {
+        0 => 97,
+        remainder => remainder,
+    };
+    check == expected
+}
+
+pub fn account_is_valid(account: &Account) -> bool {
+    match *account {
+        Account::BelgianAccountNumber { ref number, .. } => belgian_account_is_valid(number),
+        Account::IBANBelgianAccountNumber { ref number, .. } => iban_is_valid(number) && number.starts_with("BE"),
+        Account::IBANForeignAccountNumber { ref number, .. } => iban_is_valid(number),
+        Account::ForeignAccountNumber { .. } => true,
+    }
+}
+
+fn account_country(account: &Account) -> &str {
+    match *account {
+        Account::BelgianAccountNumber { ref country, .. } => country,
+        Account::IBANBelgianAccountNumber { ref number, .. } | Account::IBANForeignAccountNumber { ref number, .. } => {
+            number.get(0..2).unwrap_or("")
+        }
+        Account::ForeignAccountNumber { .. } => "",
+    }
+}
+
+fn validate_header(coda: &Coda, report: &mut ValidationReport) {
+    let bic = &coda.header.bic;
+    if !bic.is_empty() && !bic_is_valid(bic) {
+        report.error(String::from("header"), format!("Invalid BIC [{}]", bic));
+    }
+}
+
+fn validate_old_balance(coda: &Coda, report: &mut ValidationReport) {
+    let account = &coda.old_balance.account;
+    if !account_is_valid(account) {
+        report.error(String::from("old balance"), format!("Invalid account {:?}", account));
+    }
+    let currency = account.currency();
+    if !currency_is_valid(currency) {
+        report.error(String::from("old balance"), format!("Invalid currency code [{}]", currency));
+    }
+    let country = account_country(account);
+    if !country.is_empty() && !country_is_valid(country) {
+        report.error(String::from("old balance"), format!("Invalid country code [{}]", country));
+    }
+}
+
+fn movement_location(movement: &Movement) -> String {
+    format!("movement {}/{}", movement.sequence, movement.detail_sequence)
+}
+
+fn validate_movement(movement: &Movement, report: &mut ValidationReport) {
+    if movement.next_code {
+        report.warning(
+            movement_location(movement),
+            String::from("Missing continuation record announced by the next code"),
+        );
+    }
+    if let Some(ref account) = movement.counterparty_account {
+        if !account_is_valid(account) {
+            report.warning(
+                movement_location(movement),
+                format!("Invalid counterparty account {:?}", account),
+            );
+        }
+        let currency = account.currency();
+        if !currency.is_empty() && !currency_is_valid(currency) {
+            report.warning(
+                movement_location(movement),
+                format!("Invalid counterparty currency code [{}]", currency),
+            );
+        }
+    }
+}
+
+pub fn validate(coda: &Coda) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    validate_header(coda, &mut report);
+    validate_old_balance(coda, &mut report);
+    for movement in &coda.movements {
+        validate_movement(movement, &mut report);
+    }
+    report
+}
+
+#[cfg(test)]
+mod test_validation {
+    use super::*;
+    use fixtures::parse_coda;
+
+    #[test]
+    fn iban_is_valid_valid() {
+        assert_eq!(iban_is_valid("BE68539007547034"), true, "BE68539007547034 should be valid");
+        assert_eq!(iban_is_valid("NL34KRED0633083542"), true, "NL34KRED0633083542 should be valid");
+        assert_eq!(iban_is_valid("LU03 7050 5227 0227 3100"), true, "Spaces should be ignored");
+    }
+
+    #[test]
+    fn iban_is_valid_invalid() {
+        assert_eq!(iban_is_valid("BE68539007547035"), false, "Bad check digits should be invalid");
+        assert_eq!(iban_is_valid("BE6853900754703"), false, "Short BE iban should be invalid");
+        assert_eq!(iban_is_valid("068226750863"), false, "BBAN should not be an iban");
+        assert_eq!(iban_is_valid(""), false, "Empty string should be invalid");
+    }
+
+    #[test]
+    fn belgian_account_is_valid_valid() {
+        assert_eq!(belgian_account_is_valid("068226750863"), true, "068226750863 should be valid");
+        assert_eq!(belgian_account_is_valid("000000009797"), true, "Remainder 0 should use 97");
+    }
+
+    #[test]
+    fn belgian_account_is_valid_invalid() {
+        assert_eq!(belgian_account_is_valid("068226750864"), false, "Bad check digits should be invalid");
+        assert_eq!(belgian_account_is_valid("06822675086"), false, "Short number should be invalid");
+        assert_eq!(belgian_account_is_valid("06822675086A"), false, "Letters should be invalid");
+    }
+
+    #[test]
+    fn account_is_valid_foreign() {
+        let account = Account::ForeignAccountNumber {
+            number: String::from("12-3456-789"),
+            currency: String::from("USD"),
+        };
+        assert_eq!(account_is_valid(&account), true, "Foreign accounts cannot be checked");
+    }
+
+    #[test]
+    fn currency_is_valid_valid() {
+        assert_eq!(currency_is_valid("EUR"), true, "EUR should be valid");
+        assert_eq!(currency_is_valid("USD"), true, "USD should be valid");
+    }
+
+    #[test]
+    fn currency_is_valid_invalid() {
+        assert_eq!(currency_is_valid("EUX"), false, "EUX should be invalid");
+        assert_eq!(currency_is_valid("eur"), false, "Lowercase should be invalid");
+        assert_eq!(currency_is_valid(""), false, "Empty string should be invalid");
+    }
+
+    #[test]
+    fn country_is_valid_valid() {
+        assert_eq!(country_is_valid("BE"), true, "BE should be valid");
+        assert_eq!(country_is_valid("LU"), true, "LU should be valid");
+    }
+
+    #[test]
+    fn country_is_valid_invalid() {
+        assert_eq!(country_is_valid("XX"), false, "XX should be invalid");
+        assert_eq!(country_is_valid("B"), false, "B should be invalid");
+    }
+
+    #[test]
+    fn bic_is_valid_valid() {
+        assert_eq!(bic_is_valid("KREDBEBB"), true, "KREDBEBB should be valid");
+        assert_eq!(bic_is_valid("GEBABEBB36A"), true, "GEBABEBB36A should be valid");
+    }
+
+    #[test]
+    fn bic_is_valid_invalid() {
+        assert_eq!(bic_is_valid("KREDBEB"), false, "7 characters should be invalid");
+        assert_eq!(bic_is_valid("KREDXXBB"), false, "Unknown country should be invalid");
+        assert_eq!(bic_is_valid("KR3DBEBB"), false, "Digit in bank code should be invalid");
+        assert_eq!(bic_is_valid("kredbebb"), false, "Lowercase should be invalid");
+    }
+
+    #[test]
+    fn validate_coda_valid() {
+        let coda = parse_coda();
+
+        let report = validate(&coda);
+
+        assert_eq!(report.findings, vec![], "CODA.txt should have no findings");
+    }
+
+    #[test]
+    fn validate_invalid_counterparty() {
+        let mut coda = parse_coda();
+        coda.movements[0].counterparty_account = Some(Account::BelgianAccountNumber {
+            number: String::from("068226750864"),
+            currency: String::from(""),
+            country: String::from("BE"),
+        });
+
+        let report = validate(&coda);
+
+        assert_eq!(report.findings.len(), 1, "There should be one finding");
+        assert_eq!(report.findings[0].severity, Severity::Warning);
+        assert_eq!(report.findings[0].location, "movement 0001/0000");
+        assert_eq!(report.has_errors(), false, "Counterparty findings are warnings");
+    }
+
+    #[test]
+    fn validate_missing_continuation() {
+        let mut coda = parse_coda();
+        coda.movements[1].next_code = true;
+
+        let report = validate(&coda);
+
+        assert_eq!(report.findings.len(), 1, "There should be one finding");
+        assert_eq!(report.findings[0].location, "movement 0002/0000");
+    }
+
+    #[test]
+    fn validate_invalid_own_account() {
+        let mut coda = parse_coda();
+        coda.header.bic = String::from("KRED12BB");
+        coda.old_balance.account = Account::IBANBelgianAccountNumber {
+            number: String::from("BE68539007547035"),
+            currency: String::from("EUX"),
+        };
+
+        let report = validate(&coda);
+
+        assert_eq!(report.findings.len(), 3, "There should be three findings");
+        assert_eq!(report.findings[0].location, "header");
+        assert_eq!(report.findings[1].location, "old balance");
+        assert_eq!(report.findings[2].message, "Invalid currency code [EUX]");
+        assert_eq!(report.has_errors(), true, "Own account findings are errors");
+    }
+}
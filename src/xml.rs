@@ -0,0 +1,98 @@
+pub struct XmlWriter {
+    pub output: String,
+    depth: usize,
+}
+
+impl XmlWriter {
+    pub fn new() -> XmlWriter {
+        XmlWriter {
+            output: String::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn declaration(&mut self) {
+        self.instruction("xml version=\"1.0\" encoding=\"UTF-8\"");
+    }
+
+    pub fn instruction(&mut self, text: &str) {
+        self.output.push_str(&format!("<?{}?>\n", text));
+    }
+
+    pub fn escape(text: &str) -> String {
+        text.chars()
+            .map(|c| match c {
+                '&' => String::from("&amp;"),
+                '<' => String::from("&lt;"),
+                '>' => String::from("&gt;"),
+                '"' => String::from("&quot;"),
+                '\'' => String::from("&apos;"),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.output.push_str("  ");
+        }
+    }
+
+    pub fn start(&mut self, name: &str) {
+        self.indent();
+        self.output.push_str(&format!("<{}>\n", name));
+        self.depth += 1;
+    }
+
+    pub fn start_attr(&mut self, name: &str, attribute: &str, value: &str) {
+        self.indent();
+        self.output
+            .push_str(&format!("<{} {}=\"{}\">\n", name, attribute, XmlWriter::escape(value)));
+        self.depth += 1;
+    }
+
+    pub fn end(&mut self, name: &str) {
+        self.depth -= 1;
+        self.indent();
+        self.output.push_str(&format!("</{}>\n", name));
+    }
+
+    pub fn text(&mut self, name: &str, value: &str) {
+        self.indent();
+        self.output
+            .push_str(&format!("<{}>{}</{}>\n", name, XmlWriter::escape(value), name));
+    }
+
+    pub fn text_attr(&mut self, name: &str, attribute: &str, attribute_value: &str, value: &str) {
+        self.indent();
+        self.output.push_str(&format!(
+            "<{} {}=\"{}\">{}</{}>\n",
+            name,
+            attribute,
+            XmlWriter::escape(attribute_value),
+            XmlWriter::escape(value),
+            name
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test_xml {
+    use super::XmlWriter;
+
+    #[test]
+    fn xml_writer_valid() {
+        let mut writer = XmlWriter::new();
+        writer.declaration();
+        writer.start_attr("Document", "xmlns", "urn:test");
+        writer.text("Nm", "R&D <\"O'Brien\">");
+        writer.text_attr("Amt", "Ccy", "EUR", "1.000");
+        writer.end("Document");
+
+        assert_eq!(
+            writer.output,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Document xmlns=\"urn:test\">\n  \
+             <Nm>R&amp;D &lt;&quot;O&apos;Brien&quot;&gt;</Nm>\n  <Amt Ccy=\"EUR\">1.000</Amt>\n</Document>\n"
+        );
+    }
+}